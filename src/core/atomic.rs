@@ -0,0 +1,119 @@
+use super::{
+    backup_path, commit_file, file_diff, read_and_replace, write, Counters, FileOutcome,
+    FileReport, Journal, Matcher, ReplaceOptions, Replaced, Report, SarError, Selector,
+};
+use std::fs::Metadata;
+use std::path::PathBuf;
+
+/// All or nothing run over `file_paths`, see [`ReplaceOptions::atomic`]: every replacement is
+/// computed, then every write is staged in a temp file, then the staged files are renamed over the
+/// original files. The run is aborted if any file (or directory) fails, files already renamed are
+/// restored.
+pub(crate) fn process_files(
+    file_paths: &[PathBuf],
+    matchers: &[Matcher],
+    options: &ReplaceOptions,
+    selector: &mut Selector<'_>,
+    counters: &mut Counters,
+    mut journal: Option<&mut Journal>,
+    report: &mut Report,
+) {
+    let mut files: Vec<FileReport> = Vec::new();
+    let mut planned: Vec<(usize, Replaced)> = Vec::new(); // index in files, replacement
+    for file_path in file_paths {
+        if selector.is_quit() {
+            break;
+        }
+        match read_and_replace(file_path, matchers, options, selector, counters) {
+            Ok(Some(mut replaced)) => {
+                files.push(FileReport {
+                    path: file_path.clone(),
+                    matches: replaced.matches,
+                    rule_matches: std::mem::take(&mut replaced.rule_matches),
+                    key_matches: std::mem::take(&mut replaced.key_matches),
+                    outcome: FileOutcome::Found,
+                    backup: None,
+                    diff: file_diff(file_path, &replaced, options),
+                    lines: std::mem::take(&mut replaced.lines),
+                });
+                planned.push((files.len() - 1, replaced));
+            }
+            Ok(None) => (),
+            Err(error) => files.push(FileReport::failed(file_path, 0, error)),
+        }
+    }
+    let mut aborted: bool = !report.dir_errors.is_empty()
+        || files
+            .iter()
+            .any(|file| matches!(file.outcome, FileOutcome::Failed(_)));
+    let mut staged_files: Vec<write::StagedFile> = Vec::new();
+    if !aborted {
+        for (index, replaced) in &planned {
+            let file: &mut FileReport = &mut files[*index];
+            match write::stage_file(
+                replaced.content.as_bytes(),
+                &file.path,
+                options.preserve_mtime(),
+            ) {
+                Ok(staged) => staged_files.push(staged),
+                Err(error) => {
+                    file.outcome = FileOutcome::Failed(SarError::Write {
+                        path: file.path.clone(),
+                        source: error,
+                    });
+                    aborted = true;
+                    break;
+                }
+            }
+        }
+    }
+    if !aborted {
+        let mut committed: Vec<(&(usize, Replaced), Metadata)> = Vec::new();
+        // staged files left over after a failure are dropped, which removes their temp files
+        for (planned_file, staged) in planned.iter().zip(staged_files) {
+            let file: &mut FileReport = &mut files[planned_file.0];
+            let backup_path: Option<PathBuf> = backup_path(&file.path, options);
+            let original: Metadata = staged.original().clone();
+            match commit_file(
+                &file.path,
+                &planned_file.1.content,
+                staged,
+                backup_path.as_deref(),
+                journal.as_deref_mut(),
+            ) {
+                Ok(backup) => {
+                    file.outcome = FileOutcome::Edited;
+                    file.backup = backup;
+                    committed.push((planned_file, original));
+                }
+                Err(error) => {
+                    file.outcome = FileOutcome::Failed(error);
+                    aborted = true;
+                    break;
+                }
+            }
+        }
+        if aborted {
+            for ((index, replaced), original) in committed.into_iter().rev() {
+                let file: &mut FileReport = &mut files[*index];
+                match write::restore_file(replaced.original.as_bytes(), &file.path, &original) {
+                    Ok(()) => {
+                        file.outcome = FileOutcome::RolledBack;
+                        if let Some(journal) = journal.as_deref_mut() {
+                            // a stale entry is reported as changed since the run by undo
+                            let _ = journal.forget(&file.path);
+                        }
+                    }
+                    Err(error) => {
+                        file.outcome = FileOutcome::Failed(SarError::Rollback {
+                            path: file.path.clone(),
+                            source: error,
+                        })
+                    }
+                }
+            }
+        }
+    }
+    report.aborted = aborted;
+    report.files.extend(files);
+}
@@ -0,0 +1,140 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+/// Where to keep the pre-edit version of every edited file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backup {
+    /// Sibling copy, named after the original file plus the suffix, e.g. `.orig` or `.bak`
+    Suffix(String),
+    /// Mirror tree under the given directory, keeping the relative paths of the searched directory
+    Directory(PathBuf),
+}
+
+impl Backup {
+    /// Backup location of `file_path`, found while walking `directory`
+    pub fn path_for(&self, directory: &Path, file_path: &Path) -> PathBuf {
+        match self {
+            Backup::Suffix(suffix) => {
+                let mut file_name = file_path.file_name().unwrap_or_default().to_os_string();
+                file_name.push(suffix);
+                file_path.with_file_name(file_name)
+            }
+            Backup::Directory(backup_directory) => {
+                backup_directory.join(file_path.strip_prefix(directory).unwrap_or(file_path))
+            }
+        }
+    }
+
+    /// True for backup files made by an earlier (or the current) run, including the numbered ones
+    /// of [`create_backup`], which should not be searched. A backup directory is skipped as a
+    /// whole, see [`ReplaceOptions::state_dirs`](super::ReplaceOptions::state_dirs)
+    pub(crate) fn is_backup_file(&self, path: &Path) -> bool {
+        match self {
+            Backup::Suffix(suffix) => path
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .map(|file_name| {
+                    let unnumbered: &str = match file_name.rsplit_once('.') {
+                        Some((name, number))
+                            if !number.is_empty()
+                                && number.bytes().all(|byte| byte.is_ascii_digit()) =>
+                        {
+                            name
+                        }
+                        _ => file_name,
+                    };
+                    file_name.ends_with(suffix.as_str()) || unnumbered.ends_with(suffix.as_str())
+                })
+                .unwrap_or(false),
+            Backup::Directory(_) => false,
+        }
+    }
+}
+
+/// Copies `file_path` to `backup_path`, creating the parent directories of `backup_path` as needed.
+/// An existing backup, e.g. of an earlier run, is never overwritten: the copy is named after
+/// `backup_path` plus the first free `.1`, `.2`, ... instead. Returns the path of the copy
+pub(crate) fn create_backup(file_path: &Path, backup_path: &Path) -> Result<PathBuf> {
+    if let Some(parent) = backup_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut number: usize = 0;
+    loop {
+        let path: PathBuf = match number {
+            0 => backup_path.to_path_buf(),
+            _ => {
+                let mut file_name = backup_path.file_name().unwrap_or_default().to_os_string();
+                file_name.push(format!(".{}", number));
+                backup_path.with_file_name(file_name)
+            }
+        };
+        // the name is taken before the copy, so that two runs never pick the same one
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => {
+                fs::copy(file_path, &path)?;
+                return Ok(path);
+            }
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => number += 1,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::backup::{create_backup, Backup};
+    use std::fs::{read_to_string, write};
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    #[test]
+    fn path_for_suffix() {
+        let backup: Backup = Backup::Suffix(".orig".to_string());
+        assert_eq!(
+            backup.path_for(Path::new("dir"), Path::new("dir/dir1/test.json")),
+            PathBuf::from("dir/dir1/test.json.orig")
+        );
+    }
+
+    #[test]
+    fn path_for_directory() {
+        let backup: Backup = Backup::Directory(PathBuf::from("backup"));
+        assert_eq!(
+            backup.path_for(Path::new("dir"), Path::new("dir/dir1/test.json")),
+            PathBuf::from("backup/dir1/test.json")
+        );
+    }
+
+    #[test]
+    fn is_backup_suffix() {
+        let backup: Backup = Backup::Suffix(".orig".to_string());
+        assert!(backup.is_backup_file(Path::new("dir/test.json.orig")));
+        assert!(backup.is_backup_file(Path::new("dir/test.json.orig.12")));
+        assert!(!backup.is_backup_file(Path::new("dir/test.json")));
+        assert!(!backup.is_backup_file(Path::new("dir/test.json.1")));
+    }
+
+    #[test]
+    fn create_backup_keeps_existing_backups() {
+        let directory: TempDir = tempfile::tempdir().unwrap();
+        let path: PathBuf = directory.path().join("test.txt");
+        let backup_path: PathBuf = directory.path().join("backup").join("test.txt.orig");
+        for content in ["first\n", "second\n", "third\n"] {
+            write(&path, content).unwrap();
+            create_backup(&path, &backup_path).unwrap();
+        }
+        let backups: Vec<String> = ["", ".1", ".2"]
+            .iter()
+            .map(|number| {
+                read_to_string(
+                    directory
+                        .path()
+                        .join(format!("backup/test.txt.orig{}", number)),
+                )
+                .unwrap()
+            })
+            .collect();
+        assert_eq!(backups, vec!["first\n", "second\n", "third\n"]);
+    }
+}
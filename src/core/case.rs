@@ -0,0 +1,179 @@
+use heck::{ToKebabCase, ToLowerCamelCase, ToShoutySnakeCase, ToSnakeCase, ToUpperCamelCase};
+
+/// Case of the letters of a matched text, see [`preserve_case`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CasePattern {
+    /// `widget`
+    Lower,
+    /// `WIDGET`
+    Upper,
+    /// `Widget`, also a single uppercase letter
+    Title,
+    /// `wIdGeT`, `WidgetFactory`
+    Mixed,
+}
+
+/// `None` if the text has no letter with a case
+fn case_pattern(text: &str) -> Option<CasePattern> {
+    let mut letters = text
+        .chars()
+        .filter(|c| c.is_lowercase() || c.is_uppercase());
+    let first: char = letters.next()?;
+    let rest: Vec<char> = letters.collect();
+    Some(if first.is_lowercase() {
+        if rest.iter().all(|c| c.is_lowercase()) {
+            CasePattern::Lower
+        } else {
+            CasePattern::Mixed
+        }
+    } else if rest.iter().all(|c| c.is_lowercase()) {
+        CasePattern::Title
+    } else if rest.iter().all(|c| c.is_uppercase()) {
+        CasePattern::Upper
+    } else {
+        CasePattern::Mixed
+    })
+}
+
+/// Applies the case of `matched` to `replacement`: lower, upper or title case. A mixed case is
+/// copied letter by letter if both have the same number of characters, otherwise `replacement`
+/// is left as is
+pub(crate) fn preserve_case(matched: &str, replacement: &str) -> String {
+    match case_pattern(matched) {
+        None => replacement.to_string(),
+        Some(CasePattern::Lower) => replacement.to_lowercase(),
+        Some(CasePattern::Upper) => replacement.to_uppercase(),
+        Some(CasePattern::Title) => title_case(replacement),
+        Some(CasePattern::Mixed) => {
+            if matched.chars().count() != replacement.chars().count() {
+                return replacement.to_string();
+            }
+            matched
+                .chars()
+                .zip(replacement.chars())
+                .map(|(matched, replacement)| {
+                    if matched.is_uppercase() {
+                        replacement.to_uppercase().to_string()
+                    } else if matched.is_lowercase() {
+                        replacement.to_lowercase().to_string()
+                    } else {
+                        replacement.to_string()
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// First letter uppercase, every other letter lowercase
+pub(crate) fn title_case(text: &str) -> String {
+    let mut title: String = String::new();
+    let mut first: bool = true;
+    for c in text.chars() {
+        if first && (c.is_lowercase() || c.is_uppercase()) {
+            title.extend(c.to_uppercase());
+            first = false;
+        } else {
+            title.extend(c.to_lowercase());
+        }
+    }
+    title
+}
+
+/// True if `name` is words of letters and digits, separated by spaces, `_` or `-` or written in
+/// camel case
+pub(crate) fn is_identifier_name(name: &str) -> bool {
+    name.chars().any(char::is_alphanumeric)
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || c == '_' || c == '-')
+}
+
+/// The camelCase, PascalCase, snake_case, kebab-case and SCREAMING_CASE variants of `name`, in
+/// this order
+pub(crate) fn identifier_variants(name: &str) -> [String; 5] {
+    [
+        name.to_lower_camel_case(),
+        name.to_upper_camel_case(),
+        name.to_snake_case(),
+        name.to_kebab_case(),
+        name.to_shouty_snake_case(),
+    ]
+}
+
+/// True if byte offset `index` of `text` is the start or end of a word of an identifier: next to
+/// a character which is not a letter or digit, or a lowercase letter or digit followed by an
+/// uppercase letter (`order|Item`)
+pub(crate) fn is_identifier_boundary(text: &str, index: usize) -> bool {
+    let before: Option<char> = text[..index].chars().next_back();
+    let after: Option<char> = text[index..].chars().next();
+    match (before, after) {
+        (Some(before), Some(after)) if before.is_alphanumeric() && after.is_alphanumeric() => {
+            (before.is_lowercase() || before.is_numeric()) && after.is_uppercase()
+        }
+        _ => true,
+    }
+}
+
+/// First letter of every word uppercase, every other letter lowercase
+pub(crate) fn title_words(text: &str) -> String {
+    let mut title: String = String::new();
+    let mut in_word: bool = false;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            if in_word {
+                title.extend(c.to_lowercase());
+            } else {
+                title.extend(c.to_uppercase());
+            }
+            in_word = true;
+        } else {
+            title.push(c);
+            in_word = false;
+        }
+    }
+    title
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::case::{identifier_variants, is_identifier_boundary, preserve_case};
+
+    #[test]
+    fn preserve_case_patterns() {
+        assert_eq!(preserve_case("widget", "Gadget"), "gadget");
+        assert_eq!(preserve_case("WIDGET", "gadget"), "GADGET");
+        assert_eq!(preserve_case("Widget", "gadget"), "Gadget");
+        assert_eq!(preserve_case("W", "gadget box"), "Gadget box");
+        assert_eq!(preserve_case("WidgetBox", "gadgetbin"), "GadgetBin");
+        assert_eq!(preserve_case("WidgetBox", "gadgetbins"), "gadgetbins");
+        assert_eq!(preserve_case("_42_", "Gadget"), "Gadget");
+        assert_eq!(preserve_case("ÉCOLE", "straße"), "STRASSE");
+    }
+
+    #[test]
+    fn identifier_variants_and_boundaries() {
+        assert_eq!(
+            identifier_variants("order item"),
+            [
+                "orderItem",
+                "OrderItem",
+                "order_item",
+                "order-item",
+                "ORDER_ITEM"
+            ]
+        );
+        assert_eq!(
+            identifier_variants("orderItem"),
+            identifier_variants("order-item")
+        );
+        let text: &str = "getOrderItem reorder_item ORDER_ITEMS";
+        let boundaries: Vec<usize> = (0..=text.len())
+            .filter(|index| is_identifier_boundary(text, *index))
+            .collect();
+        assert_eq!(
+            boundaries,
+            vec![0, 3, 8, 12, 13, 20, 21, 25, 26, 31, 32, 37]
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use super::template::CounterKind;
+use super::{CounterScope, ReplaceOptions};
+
+/// Numbers given by `${counter}` and `${file_counter}` during a run. Only accepted matches are
+/// numbered, in the order the files are walked and the matches are found in them. A file gets a
+/// number with its first accepted match which uses `${file_counter}`
+#[derive(Debug, Clone)]
+pub(crate) struct Counters {
+    scope: CounterScope,
+    /// Matches numbered so far, one per rule with [`CounterScope::Rule`], a single one otherwise
+    matches: Vec<u64>,
+    /// Files numbered so far, like `matches`
+    files: Vec<u64>,
+    /// True once the current file has a number, like `matches`
+    numbered: Vec<bool>,
+}
+
+impl Counters {
+    pub(crate) fn new(options: &ReplaceOptions) -> Counters {
+        let slots: usize = match options.counter_scope() {
+            CounterScope::Rule => options.rules().len().max(1),
+            CounterScope::Global | CounterScope::File => 1,
+        };
+        Counters {
+            scope: options.counter_scope(),
+            matches: vec![0; slots],
+            files: vec![0; slots],
+            numbered: vec![false; slots],
+        }
+    }
+
+    pub(crate) fn start_file(&mut self) {
+        self.numbered.fill(false);
+        if self.scope == CounterScope::File {
+            self.matches.fill(0);
+        }
+    }
+
+    /// How many matches (or files) were numbered before the current one, in the scope of `rule`
+    pub(crate) fn counted(&self, rule: usize, kind: CounterKind) -> u64 {
+        let slot: usize = self.slot(rule);
+        match kind {
+            CounterKind::Match => self.matches[slot],
+            CounterKind::File => self.files[slot] - u64::from(self.numbered[slot]),
+        }
+    }
+
+    /// Numbers an accepted match of `rule`, or its file if the file has no number yet
+    pub(crate) fn count(&mut self, rule: usize, kind: CounterKind) {
+        let slot: usize = self.slot(rule);
+        match kind {
+            CounterKind::Match => self.matches[slot] += 1,
+            CounterKind::File if !self.numbered[slot] => {
+                self.files[slot] += 1;
+                self.numbered[slot] = true;
+            }
+            CounterKind::File => (),
+        }
+    }
+
+    fn slot(&self, rule: usize) -> usize {
+        match self.scope {
+            CounterScope::Rule => rule,
+            CounterScope::Global | CounterScope::File => 0,
+        }
+    }
+}
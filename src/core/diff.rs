@@ -0,0 +1,59 @@
+use similar::TextDiff;
+use std::path::{Component, Path};
+
+/// Unified diff of a file, with `--- a/<path>` and `+++ b/<path>` headers so the concatenated
+/// diffs of a run can be applied with `git apply` or `patch -p1` from the searched directory.
+/// `path` is relative to the searched directory
+pub fn unified_diff(original: &str, content: &str, path: &str, context_lines: usize) -> String {
+    TextDiff::from_lines(original, content)
+        .unified_diff()
+        .context_radius(context_lines)
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string()
+}
+
+/// Path of `file_path` relative to `directory`, always separated by '/' as patch files expect
+pub(crate) fn patch_path(directory: &Path, file_path: &Path) -> String {
+    file_path
+        .strip_prefix(directory)
+        .unwrap_or(file_path)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::diff::{patch_path, unified_diff};
+    use std::path::Path;
+
+    #[test]
+    fn unified_diff_headers_and_context() {
+        let original: &str = "one\ntwo\nthree\nfour\nfive\n";
+        let content: &str = "one\ntwo\n3\nfour\nfive\n";
+        assert_eq!(
+            unified_diff(original, content, "dir1/file.txt", 1),
+            "--- a/dir1/file.txt\n+++ b/dir1/file.txt\n@@ -2,3 +2,3 @@\n two\n-three\n+3\n four\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_missing_newline() {
+        assert_eq!(
+            unified_diff("old", "new", "file.txt", 3),
+            "--- a/file.txt\n+++ b/file.txt\n@@ -1 +1 @@\n-old\n\\ No newline at end of file\n+new\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn patch_path_relative() {
+        assert_eq!(
+            patch_path(Path::new("./src"), Path::new("./src/core/mod.rs")),
+            "core/mod.rs"
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors returned by [`process_directory`](super::process_directory).
+/// Except for [`SarError::InvalidPattern`], [`SarError::InvalidTemplate`], [`SarError::InvalidIdentifier`], [`SarError::InvalidGlob`] and [`SarError::Script`], errors are collected per file or directory in the [`Report`](super::Report).
+#[derive(Debug)]
+pub enum SarError {
+    /// Search text is not a valid regex
+    InvalidPattern {
+        pattern: String,
+        /// Reason reported by the regex crate, e.g. "unclosed group"
+        message: String,
+        /// Position of the offending part of the pattern, if the regex crate reported one
+        span: Option<PatternSpan>,
+        source: regex::Error,
+    },
+    /// Replace text has an unknown filter or a filter argument which is not valid
+    InvalidTemplate { template: String, message: String },
+    /// Name of an identifier to rename is not words of letters and digits
+    InvalidIdentifier { name: String },
+    /// Include or exclude glob is not valid
+    InvalidGlob {
+        glob: String,
+        source: globset::Error,
+    },
+    /// Rules file could not be read or is not valid, every problem found is listed. Nothing was
+    /// searched
+    RulesFile { path: PathBuf, errors: Vec<String> },
+    /// Mapping file could not be read or is not valid, every problem found is listed. Nothing was
+    /// searched
+    Mapping { path: PathBuf, errors: Vec<String> },
+    /// Replace script could not be read or does not compile. Nothing was searched
+    Script { path: PathBuf, message: String },
+    /// Directory or file could not be entered while walking the tree (e.g. permission denied)
+    Walk {
+        path: Option<PathBuf>,
+        source: walkdir::Error,
+    },
+    /// File could not be opened or read
+    Read { path: PathBuf, source: io::Error },
+    /// File is not UTF-8 encoded text
+    Decode { path: PathBuf, line: usize },
+    /// Replacement of a match could not be expanded, e.g. `${1|add:1}` of a group which is not an
+    /// integer. The file was not edited
+    Replace {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// File could not be written
+    Write { path: PathBuf, source: io::Error },
+    /// Backup of the file could not be created, the file was not edited
+    Backup {
+        path: PathBuf,
+        backup_path: PathBuf,
+        source: io::Error,
+    },
+    /// File edited by an aborted atomic run could not be restored
+    Rollback { path: PathBuf, source: io::Error },
+    /// Undo journal could not be written or read
+    Journal { path: PathBuf, source: io::Error },
+    /// No journaled run with the given id, or no run left to undo if no id was given
+    RunNotFound { run_id: Option<String> },
+}
+
+/// Byte offsets (`start..end`) of the offending part of a search pattern,
+/// with the 1-based line and column of `start`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatternSpan {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SarError {
+    pub(crate) fn invalid_pattern(pattern: &str, source: regex::Error) -> SarError {
+        let (message, span): (String, Option<PatternSpan>) =
+            match regex_syntax::Parser::new().parse(pattern) {
+                Err(regex_syntax::Error::Parse(error)) => (
+                    error.kind().to_string(),
+                    Some(PatternSpan::from(error.span())),
+                ),
+                Err(regex_syntax::Error::Translate(error)) => (
+                    error.kind().to_string(),
+                    Some(PatternSpan::from(error.span())),
+                ),
+                _ => (source.to_string(), None), // e.g. compiled regex exceeds size limit
+            };
+        SarError::InvalidPattern {
+            pattern: pattern.to_string(),
+            message,
+            span,
+            source,
+        }
+    }
+
+    /// File or directory the error relates to, if any
+    pub fn path(&self) -> Option<&std::path::Path> {
+        match self {
+            SarError::InvalidPattern { .. }
+            | SarError::InvalidIdentifier { .. }
+            | SarError::InvalidTemplate { .. }
+            | SarError::InvalidGlob { .. }
+            | SarError::RunNotFound { .. } => None,
+            SarError::Walk { path, .. } => path.as_deref(),
+            SarError::Read { path, .. }
+            | SarError::Decode { path, .. }
+            | SarError::Replace { path, .. }
+            | SarError::Write { path, .. }
+            | SarError::Backup { path, .. }
+            | SarError::Rollback { path, .. }
+            | SarError::Journal { path, .. }
+            | SarError::RulesFile { path, .. }
+            | SarError::Mapping { path, .. }
+            | SarError::Script { path, .. } => Some(path.as_path()),
+        }
+    }
+}
+
+impl From<&regex_syntax::ast::Span> for PatternSpan {
+    fn from(span: &regex_syntax::ast::Span) -> Self {
+        PatternSpan {
+            start: span.start.offset,
+            end: span.end.offset,
+            line: span.start.line,
+            column: span.start.column,
+        }
+    }
+}
+
+impl fmt::Display for SarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SarError::InvalidPattern {
+                pattern,
+                message,
+                span: Some(span),
+                ..
+            } => write!(
+                f,
+                "Invalid search pattern \"{}\": {} (line {}, column {})",
+                pattern, message, span.line, span.column
+            ),
+            SarError::InvalidPattern {
+                pattern, message, ..
+            } => write!(f, "Invalid search pattern \"{}\": {}", pattern, message),
+            SarError::InvalidTemplate { template, message } => {
+                write!(f, "Invalid replace text \"{}\": {}", template, message)
+            }
+            SarError::InvalidIdentifier { name } => write!(
+                f,
+                "Invalid identifier name \"{}\": expected words of letters and digits, separated by spaces, '_' or '-'",
+                name
+            ),
+            SarError::InvalidGlob { glob, source } => {
+                write!(f, "Invalid glob \"{}\": {}", glob, source.kind())
+            }
+            SarError::RulesFile { path, errors } => {
+                write!(f, "Invalid rules file: {}", path.display())?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+            SarError::Mapping { path, errors } => {
+                write!(f, "Invalid mapping file: {}", path.display())?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+            SarError::Script { path, message } => {
+                write!(f, "Invalid replace script: {} -- {}", path.display(), message)
+            }
+            SarError::Walk {
+                path: Some(path),
+                source,
+            } => write!(f, "Error entering: {} -- {}", path.display(), source),
+            SarError::Walk { path: None, source } => write!(f, "Error: {}", source),
+            SarError::Read { path, source } => {
+                write!(f, "Error reading file: {} -- {}", path.display(), source)
+            }
+            SarError::Decode { path, line } => write!(
+                f,
+                "Error reading file: {} -- line {} is not valid UTF-8",
+                path.display(),
+                line
+            ),
+            SarError::Replace {
+                path,
+                line,
+                message,
+            } => write!(
+                f,
+                "Error replacing in file: {} -- line {}: {}",
+                path.display(),
+                line,
+                message
+            ),
+            SarError::Write { path, source } => {
+                write!(f, "Error writing file: {} -- {}", path.display(), source)
+            }
+            SarError::Backup {
+                path,
+                backup_path,
+                source,
+            } => write!(
+                f,
+                "Error backing up file: {} to: {} -- {}",
+                path.display(),
+                backup_path.display(),
+                source
+            ),
+            SarError::Rollback { path, source } => write!(
+                f,
+                "Error restoring file after aborted atomic run: {} -- {}",
+                path.display(),
+                source
+            ),
+            SarError::Journal { path, source } => {
+                write!(
+                    f,
+                    "Error accessing journal: {} -- {}",
+                    path.display(),
+                    source
+                )
+            }
+            SarError::RunNotFound {
+                run_id: Some(run_id),
+            } => write!(f, "Run not found: {}", run_id),
+            SarError::RunNotFound { run_id: None } => write!(f, "No run found which can be undone"),
+        }
+    }
+}
+
+impl std::error::Error for SarError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SarError::InvalidPattern { source, .. } => Some(source),
+            SarError::InvalidGlob { source, .. } => Some(source),
+            SarError::Walk { source, .. } => Some(source),
+            SarError::Read { source, .. }
+            | SarError::Write { source, .. }
+            | SarError::Backup { source, .. }
+            | SarError::Rollback { source, .. }
+            | SarError::Journal { source, .. } => Some(source),
+            SarError::Decode { .. }
+            | SarError::Replace { .. }
+            | SarError::InvalidIdentifier { .. }
+            | SarError::InvalidTemplate { .. }
+            | SarError::RulesFile { .. }
+            | SarError::Mapping { .. }
+            | SarError::Script { .. }
+            | SarError::RunNotFound { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use super::SarError;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::{Component, Path};
+
+/// Which files a run or a rule applies to. Globs are matched against the path relative to the
+/// searched directory, `*` also matches `/`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FileFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    ignored_dirs: Vec<String>,
+}
+
+impl FileFilter {
+    pub(crate) fn build(
+        include: &[String],
+        exclude: &[String],
+        ignored_dirs: &[String],
+    ) -> Result<FileFilter, SarError> {
+        Ok(FileFilter {
+            include: build_glob_set(include)?,
+            exclude: build_glob_set(exclude)?,
+            ignored_dirs: ignored_dirs.to_vec(),
+        })
+    }
+
+    /// True if the filter lets every file through
+    pub(crate) fn is_empty(&self) -> bool {
+        self.include.is_none() && self.exclude.is_none() && self.ignored_dirs.is_empty()
+    }
+
+    pub(crate) fn is_match(&self, relative_path: &Path) -> bool {
+        let in_ignored_dir = || {
+            relative_path
+                .parent()
+                .map(|parent| {
+                    parent.components().any(|component| match component {
+                        Component::Normal(name) => {
+                            self.ignored_dirs.iter().any(|dir| name == dir.as_str())
+                        }
+                        _ => false,
+                    })
+                })
+                .unwrap_or(false)
+        };
+        self.include
+            .as_ref()
+            .map(|include| include.is_match(relative_path))
+            .unwrap_or(true)
+            && !self
+                .exclude
+                .as_ref()
+                .map(|exclude| exclude.is_match(relative_path))
+                .unwrap_or(false)
+            && !in_ignored_dir()
+    }
+}
+
+/// `None` if there are no globs
+fn build_glob_set(globs: &[String]) -> Result<Option<GlobSet>, SarError> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+    let mut builder: GlobSetBuilder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).map_err(|error| SarError::InvalidGlob {
+            glob: glob.clone(),
+            source: error,
+        })?);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|error| SarError::InvalidGlob {
+            glob: globs.join(", "),
+            source: error,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::filter::FileFilter;
+    use crate::core::SarError;
+    use std::path::Path;
+
+    #[test]
+    fn file_filter_globs_and_ignored_dirs() {
+        let filter: FileFilter = FileFilter::build(
+            &[String::from("src/**/*.rs"), String::from("*.toml")],
+            &[String::from("**/generated/**")],
+            &[String::from("vendor")],
+        )
+        .unwrap();
+        assert!(filter.is_match(Path::new("src/core/mod.rs")));
+        assert!(filter.is_match(Path::new("Cargo.toml")));
+        assert!(filter.is_match(Path::new("crates/a/Cargo.toml")));
+        assert!(!filter.is_match(Path::new("src/generated/api.rs")));
+        assert!(!filter.is_match(Path::new("src/vendor/lib.rs")));
+        assert!(!filter.is_match(Path::new("README.md")));
+        assert!(FileFilter::build(&[], &[], &[]).unwrap().is_empty());
+        match FileFilter::build(&[String::from("src/[a")], &[], &[]) {
+            Err(SarError::InvalidGlob { glob, .. }) => assert_eq!(glob, "src/[a"),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use super::MatchSpan;
+use std::path::Path;
+
+/// A single match offered for confirmation by
+/// [`process_directory_interactive`](super::process_directory_interactive)
+#[derive(Debug)]
+pub struct MatchCandidate<'a> {
+    pub path: &'a Path,
+    /// Line number of the match, starting at 1
+    pub line_number: usize,
+    /// Line number where the match ends, the same as `line_number` unless the match spans several
+    /// lines
+    pub end_line_number: usize,
+    /// Every line of the file as read, including line endings, to display context around the match
+    pub lines: &'a [String],
+    pub span: &'a MatchSpan,
+}
+
+/// Answer to a [`MatchCandidate`], like `git add -p`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Replace this match
+    Yes,
+    /// Leave this match
+    No,
+    /// Replace this match and every following match in the file without asking
+    AllInFile,
+    /// Leave this match and every following match in the file without asking
+    SkipFile,
+    /// Leave this match and every following match, stop the run. Replacements accepted so far are
+    /// written
+    Quit,
+}
+
+/// Decides which matches get replaced, all of them unless a confirmation is given
+pub(crate) struct Selector<'a> {
+    confirm: Option<&'a mut dyn FnMut(&MatchCandidate<'_>) -> Decision>,
+    /// Decision for the rest of the current file
+    file_decision: Option<bool>,
+    quit: bool,
+}
+
+impl<'a> Selector<'a> {
+    pub(crate) fn all() -> Selector<'a> {
+        Selector {
+            confirm: None,
+            file_decision: None,
+            quit: false,
+        }
+    }
+
+    pub(crate) fn confirming(
+        confirm: &'a mut dyn FnMut(&MatchCandidate<'_>) -> Decision,
+    ) -> Selector<'a> {
+        Selector {
+            confirm: Some(confirm),
+            file_decision: None,
+            quit: false,
+        }
+    }
+
+    pub(crate) fn start_file(&mut self) {
+        self.file_decision = None;
+    }
+
+    /// True once the run was stopped, no further file must be processed
+    pub(crate) fn is_quit(&self) -> bool {
+        self.quit
+    }
+
+    pub(crate) fn accept(&mut self, candidate: &MatchCandidate<'_>) -> bool {
+        if self.quit {
+            return false;
+        }
+        if let Some(accept) = self.file_decision {
+            return accept;
+        }
+        match self.confirm.as_mut() {
+            None => true,
+            Some(confirm) => match confirm(candidate) {
+                Decision::Yes => true,
+                Decision::No => false,
+                Decision::AllInFile => {
+                    self.file_decision = Some(true);
+                    true
+                }
+                Decision::SkipFile => {
+                    self.file_decision = Some(false);
+                    false
+                }
+                Decision::Quit => {
+                    self.quit = true;
+                    false
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::interactive::{Decision, MatchCandidate, Selector};
+    use crate::core::MatchSpan;
+    use std::path::Path;
+
+    #[test]
+    fn selector_remembers_file_and_quit_decisions() {
+        let mut answers: Vec<Decision> = vec![Decision::Quit, Decision::No, Decision::AllInFile];
+        let mut asked: usize = 0;
+        let mut confirm = |_: &MatchCandidate<'_>| {
+            asked += 1;
+            answers.pop().unwrap()
+        };
+        let mut selector: Selector = Selector::confirming(&mut confirm);
+        let span: MatchSpan = MatchSpan {
+            start: 0,
+            end: 1,
+            replacement: String::new(),
+            rule: 0,
+            key: None,
+        };
+        let candidate: MatchCandidate = MatchCandidate {
+            path: Path::new("file.txt"),
+            line_number: 1,
+            end_line_number: 1,
+            lines: &[],
+            span: &span,
+        };
+        let accepted: Vec<bool> = (0..3).map(|_| selector.accept(&candidate)).collect();
+        assert_eq!(accepted, vec![true, true, true]);
+        selector.start_file();
+        let accepted: Vec<bool> = (0..3).map(|_| selector.accept(&candidate)).collect();
+        assert_eq!(accepted, vec![false, false, false]);
+        assert!(selector.is_quit());
+        assert_eq!(asked, 3);
+    }
+}
@@ -0,0 +1,605 @@
+use super::{write, ReplaceOptions, Rule, SarError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const JOURNAL_FILE_NAME: &str = "journal.json";
+const ENTRIES_FILE_NAME: &str = "entries.jsonl";
+const ORIGINALS_DIR_NAME: &str = "files";
+
+/// Record of a single (non dry) run, used by [`undo`] to restore the edited files.
+/// Stored as `<state directory>/<run id>/journal.json`, with a copy of every edited file's original
+/// content under `<state directory>/<run id>/files`. While the run is in progress every edit is
+/// appended to `<state directory>/<run id>/entries.jsonl`, which is folded into `journal.json` at
+/// the end of the run, or when the journal of a run which did not finish is read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Journal {
+    pub run_id: String,
+    /// Milliseconds since the Unix epoch
+    pub started_at: u128,
+    pub arguments: JournalArguments,
+    pub files: Vec<JournalEntry>,
+    pub undone: bool,
+    #[serde(skip)]
+    run_directory: PathBuf,
+    /// Name of the next copy of an original file in the run directory
+    #[serde(skip)]
+    next_original: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalArguments {
+    pub directory: PathBuf,
+    pub file_extensions: Vec<String>,
+    pub ignored_dirs: Vec<String>,
+    /// Every rule of the run in the order given
+    pub rules: Vec<JournalRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalRule {
+    pub search: String,
+    pub replace: String,
+    /// Match options of the rule, named like the flags of a rules file, e.g. `ignore-case`
+    pub flags: Vec<String>,
+    /// Mapping file whose keys the rule replaces
+    pub map_file: Option<PathBuf>,
+    /// Replace script run for every match of the rule
+    pub script: Option<PathBuf>,
+}
+
+impl JournalRule {
+    fn new(rule: &Rule) -> JournalRule {
+        let flags: Vec<String> = [
+            ("fixed-strings", rule.fixed_strings),
+            ("literal-replace", rule.literal_replace),
+            ("case-spans", rule.case_spans),
+            ("ignore-case", rule.ignore_case),
+            ("smart-case", rule.smart_case),
+            ("word-regexp", rule.word_regexp),
+            ("preserve-case", rule.preserve_case),
+            ("rename-identifier", rule.identifier.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(flag, _)| String::from(flag))
+        .collect();
+        JournalRule {
+            search: rule.search.clone(),
+            replace: rule.replace.clone(),
+            flags,
+            map_file: rule.map_file.clone(),
+            script: rule.script.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Absolute path of the edited file
+    pub path: PathBuf,
+    /// SHA-256 of the file content before the edit
+    pub hash_before: String,
+    /// SHA-256 of the file content after the edit
+    pub hash_after: String,
+    /// Copy of the original content, relative to the run directory
+    pub original: PathBuf,
+}
+
+/// Line of the entries file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JournalEvent {
+    Recorded(JournalEntry),
+    /// Copy of the original content of the entry which was dropped
+    Forgotten(PathBuf),
+}
+
+/// Result of [`undo`]
+#[derive(Debug)]
+pub struct UndoReport {
+    pub run_id: String,
+    pub files: Vec<UndoFile>,
+}
+
+#[derive(Debug)]
+pub struct UndoFile {
+    pub path: PathBuf,
+    pub outcome: UndoOutcome,
+}
+
+#[derive(Debug)]
+pub enum UndoOutcome {
+    /// Original content restored
+    Restored,
+    /// File changed (or was removed) after the run, left untouched
+    Drifted,
+    /// Original content could not be restored
+    Failed(SarError),
+}
+
+/// `SAR_STATE_DIR` if set, otherwise the platform's local state directory
+/// (`$XDG_STATE_HOME/sar`, `~/.local/state/sar` or `%LOCALAPPDATA%\sar`)
+pub fn default_state_dir() -> Option<PathBuf> {
+    if let Some(state_dir) = env::var_os("SAR_STATE_DIR") {
+        return Some(PathBuf::from(state_dir));
+    }
+    if cfg!(windows) {
+        return env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("sar"));
+    }
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })
+        .map(|dir| dir.join("sar"))
+}
+
+impl Journal {
+    /// Creates a new run directory under `state_dir`
+    pub(crate) fn create(state_dir: &Path, options: &ReplaceOptions) -> Result<Journal, SarError> {
+        let now: SystemTime = SystemTime::now();
+        let timestamp: String = format_timestamp(now);
+        fs::create_dir_all(state_dir).map_err(|error| journal_error(state_dir, error))?;
+        let mut attempt: usize = 0;
+        let (run_id, run_directory): (String, PathBuf) = loop {
+            let run_id: String = match attempt {
+                0 => timestamp.clone(),
+                _ => format!("{}-{}", timestamp, attempt),
+            };
+            let run_directory: PathBuf = state_dir.join(&run_id);
+            match fs::create_dir(&run_directory) {
+                Ok(()) => break (run_id, run_directory),
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => attempt += 1,
+                Err(error) => return Err(journal_error(&run_directory, error)),
+            }
+        };
+        let journal: Journal = Journal {
+            run_id,
+            started_at: now
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_millis())
+                .unwrap_or(0),
+            arguments: JournalArguments {
+                directory: absolute(options.directory()),
+                file_extensions: options.file_extensions().to_vec(),
+                ignored_dirs: options.ignored_dirs().to_vec(),
+                rules: options.rules().iter().map(JournalRule::new).collect(),
+            },
+            files: Vec::new(),
+            undone: false,
+            run_directory,
+            next_original: 0,
+        };
+        journal.save()?;
+        Ok(journal)
+    }
+
+    /// Loads the journal of `run_id`, or of the latest run which was not undone yet
+    pub fn load(state_dir: &Path, run_id: Option<&str>) -> Result<Journal, SarError> {
+        match run_id {
+            // never join anything else than a run id to the state directory, e.g. ../x
+            Some(run_id) if !is_run_id(run_id) => Err(SarError::RunNotFound {
+                run_id: Some(run_id.to_string()),
+            }),
+            Some(run_id) => {
+                Journal::read(&state_dir.join(run_id)).map_err(|error| match error.kind() {
+                    ErrorKind::NotFound => SarError::RunNotFound {
+                        run_id: Some(run_id.to_string()),
+                    },
+                    _ => journal_error(&state_dir.join(run_id), error),
+                })
+            }
+            None => history(state_dir)?
+                .into_iter()
+                .rev()
+                .find(|journal| !journal.undone)
+                .ok_or(SarError::RunNotFound { run_id: None }),
+        }
+    }
+
+    fn read(run_directory: &Path) -> io::Result<Journal> {
+        let file: File = File::open(run_directory.join(JOURNAL_FILE_NAME))?;
+        let mut journal: Journal = serde_json::from_reader(io::BufReader::new(file))?;
+        journal.run_directory = run_directory.to_path_buf();
+        let entries: File = match File::open(run_directory.join(ENTRIES_FILE_NAME)) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(journal),
+            Err(error) => return Err(error),
+        };
+        for line in io::BufReader::new(entries).lines() {
+            // the last line of a run which was killed may be cut off
+            match serde_json::from_str::<JournalEvent>(&line?) {
+                Ok(event) => journal.apply(event),
+                Err(_) => break,
+            }
+        }
+        Ok(journal)
+    }
+
+    /// Applies an event of the entries file, the events of a run which did not finish may already
+    /// be in journal.json
+    fn apply(&mut self, event: JournalEvent) {
+        match event {
+            JournalEvent::Recorded(entry) => {
+                if !self
+                    .files
+                    .iter()
+                    .any(|file| file.original == entry.original)
+                {
+                    self.files.push(entry);
+                }
+            }
+            JournalEvent::Forgotten(original) => {
+                self.files.retain(|file| file.original != original)
+            }
+        }
+    }
+
+    fn append(&self, event: &JournalEvent) -> Result<(), SarError> {
+        let path: PathBuf = self.run_directory.join(ENTRIES_FILE_NAME);
+        let mut line: Vec<u8> =
+            serde_json::to_vec(event).map_err(|error| journal_error(&path, error.into()))?;
+        line.push(b'\n');
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(&line))
+            .map_err(|error| journal_error(&path, error))
+    }
+
+    /// Replaces journal.json with a temp file renamed over it, then removes the entries file which
+    /// it now contains
+    pub(crate) fn save(&self) -> Result<(), SarError> {
+        let path: PathBuf = self.run_directory.join(JOURNAL_FILE_NAME);
+        let content: Vec<u8> =
+            serde_json::to_vec_pretty(self).map_err(|error| journal_error(&path, error.into()))?;
+        tempfile::NamedTempFile::new_in(&self.run_directory)
+            .and_then(|mut temp_file| {
+                temp_file.write_all(&content)?;
+                temp_file.persist(&path).map_err(|error| error.error)
+            })
+            .map_err(|error| journal_error(&path, error))?;
+        let entries: PathBuf = self.run_directory.join(ENTRIES_FILE_NAME);
+        match fs::remove_file(&entries) {
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                Err(journal_error(&entries, error))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Copies the original content of `file_path` into the run directory and records the edit.
+    /// Must be called before `content` is written to the file.
+    pub(crate) fn record(&mut self, file_path: &Path, content: &[u8]) -> Result<(), SarError> {
+        let original: PathBuf = Path::new(ORIGINALS_DIR_NAME).join(self.next_original.to_string());
+        self.next_original += 1;
+        let original_path: PathBuf = self.run_directory.join(&original);
+        let original_content: Vec<u8> = fs::read(file_path).map_err(|error| SarError::Read {
+            path: file_path.to_path_buf(),
+            source: error,
+        })?;
+        fs::create_dir_all(self.run_directory.join(ORIGINALS_DIR_NAME))
+            .and_then(|()| fs::write(&original_path, &original_content))
+            .map_err(|error| journal_error(&original_path, error))?;
+        let entry: JournalEntry = JournalEntry {
+            path: absolute(file_path),
+            hash_before: hash(&original_content),
+            hash_after: hash(content),
+            original,
+        };
+        self.append(&JournalEvent::Recorded(entry.clone()))?;
+        self.files.push(entry);
+        Ok(())
+    }
+
+    /// Drops the recorded edit of `file_path`, when writing the file failed or was rolled back
+    pub(crate) fn forget(&mut self, file_path: &Path) -> Result<(), SarError> {
+        let path: PathBuf = absolute(file_path);
+        if let Some(index) = self.files.iter().rposition(|entry| entry.path == path) {
+            let entry: JournalEntry = self.files.remove(index);
+            self.append(&JournalEvent::Forgotten(entry.original.clone()))?;
+            let _ = fs::remove_file(self.run_directory.join(entry.original));
+        }
+        Ok(())
+    }
+
+    /// Removes the run directory of a run which did not edit any file
+    pub(crate) fn discard(self) {
+        let _ = fs::remove_dir_all(&self.run_directory);
+    }
+}
+
+/// All journaled runs in `state_dir`, oldest first
+pub fn history(state_dir: &Path) -> Result<Vec<Journal>, SarError> {
+    let entries: fs::ReadDir = match fs::read_dir(state_dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(journal_error(state_dir, error)),
+    };
+    let mut journals: Vec<Journal> = Vec::new();
+    for entry in entries {
+        let entry: fs::DirEntry = entry.map_err(|error| journal_error(state_dir, error))?;
+        if entry.path().join(JOURNAL_FILE_NAME).is_file() {
+            journals.push(
+                Journal::read(&entry.path())
+                    .map_err(|error| journal_error(&entry.path(), error))?,
+            );
+        }
+    }
+    journals.sort_by(|a, b| (a.started_at, &a.run_id).cmp(&(b.started_at, &b.run_id)));
+    Ok(journals)
+}
+
+/// Removes every run in `state_dir` but the latest `keep` ones, returns the removed runs
+pub fn prune(state_dir: &Path, keep: usize) -> Result<Vec<Journal>, SarError> {
+    let mut journals: Vec<Journal> = history(state_dir)?;
+    let removed: Vec<Journal> = journals
+        .drain(..journals.len().saturating_sub(keep))
+        .collect();
+    for journal in &removed {
+        fs::remove_dir_all(&journal.run_directory)
+            .map_err(|error| journal_error(&journal.run_directory, error))?;
+    }
+    Ok(removed)
+}
+
+/// Restores the files edited by `run_id` (or by the latest run which was not undone yet).
+/// A file is only restored if its content still matches the content written by the run. The run is
+/// marked undone once every file is restored, until then undo can be run again, files restored
+/// before are left as they are.
+pub fn undo(state_dir: &Path, run_id: Option<&str>) -> Result<UndoReport, SarError> {
+    let mut journal: Journal = Journal::load(state_dir, run_id)?;
+    let mut files: Vec<UndoFile> = Vec::new();
+    // restore in reverse order, in case a file was edited more than once
+    for entry in journal.files.iter().rev() {
+        let outcome: UndoOutcome = match fs::read(&entry.path) {
+            Ok(content) if hash(&content) == entry.hash_before => UndoOutcome::Restored,
+            Ok(content) if hash(&content) == entry.hash_after => {
+                let original_path: PathBuf = journal.run_directory.join(&entry.original);
+                match fs::read(&original_path) {
+                    Ok(original) => match write::write_file(&original, &entry.path, false) {
+                        Ok(()) => UndoOutcome::Restored,
+                        Err(error) => UndoOutcome::Failed(SarError::Write {
+                            path: entry.path.clone(),
+                            source: error,
+                        }),
+                    },
+                    Err(error) => UndoOutcome::Failed(journal_error(&original_path, error)),
+                }
+            }
+            Ok(_) => UndoOutcome::Drifted,
+            Err(error) if error.kind() == ErrorKind::NotFound => UndoOutcome::Drifted,
+            Err(error) => UndoOutcome::Failed(SarError::Read {
+                path: entry.path.clone(),
+                source: error,
+            }),
+        };
+        files.push(UndoFile {
+            path: entry.path.clone(),
+            outcome,
+        });
+    }
+    if files
+        .iter()
+        .all(|file| matches!(file.outcome, UndoOutcome::Restored))
+    {
+        journal.undone = true;
+        journal.save()?;
+    }
+    Ok(UndoReport {
+        run_id: journal.run_id,
+        files,
+    })
+}
+
+/// Hex encoded SHA-256 of `content`
+pub(crate) fn hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn journal_error(path: &Path, error: io::Error) -> SarError {
+    SarError::Journal {
+        path: path.to_path_buf(),
+        source: error,
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// True for the ids given by [`Journal::create`], e.g. `20221218T093512Z` or `20221218T093512Z-1`
+fn is_run_id(run_id: &str) -> bool {
+    let (timestamp, attempt): (&str, &str) = run_id.split_once('-').unwrap_or((run_id, "0"));
+    let is_number = |bytes: &[u8]| !bytes.is_empty() && bytes.iter().all(u8::is_ascii_digit);
+    match timestamp.as_bytes() {
+        [date @ .., b'T', _, _, _, _, _, _, b'Z'] if date.len() == 8 => {
+            is_number(date)
+                && is_number(&timestamp.as_bytes()[9..15])
+                && is_number(attempt.as_bytes())
+        }
+        _ => false,
+    }
+}
+
+/// UTC timestamp, e.g. `20221218T093512Z`
+fn format_timestamp(time: SystemTime) -> String {
+    let seconds: u64 = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, seconds_of_day): (u64, u64) = (seconds / 86_400, seconds % 86_400);
+    // civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z: u64 = days + 719_468;
+    let era: u64 = z / 146_097;
+    let day_of_era: u64 = z - era * 146_097;
+    let year_of_era: u64 =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year: u64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp: u64 = (5 * day_of_year + 2) / 153;
+    let day: u64 = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month: u64 = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year: u64 = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::journal::{
+        format_timestamp, hash, history, is_run_id, prune, Journal, ENTRIES_FILE_NAME,
+    };
+    use crate::core::{JournalRule, ReplaceOptions, Rule, SarError};
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tempfile::TempDir;
+
+    #[test]
+    fn journal_entries_are_appended_and_folded_on_save() {
+        let directory: TempDir = tempfile::tempdir().unwrap();
+        let state_dir: PathBuf = directory.path().join("state");
+        let files: Vec<PathBuf> = (0..3)
+            .map(|index| {
+                let path: PathBuf = directory.path().join(format!("{}.txt", index));
+                std::fs::write(&path, "positive\n").unwrap();
+                path
+            })
+            .collect();
+        let mut journal: Journal =
+            Journal::create(&state_dir, &ReplaceOptions::builder().build()).unwrap();
+        for path in &files {
+            journal.record(path, b"negative\n").unwrap();
+        }
+        journal.forget(&files[1]).unwrap();
+        // a run which did not finish is read from its entries file
+        let read: Journal = Journal::read(&journal.run_directory).unwrap();
+        assert_eq!(read.files.len(), 2);
+        assert!(read.files[1].path.ends_with("2.txt"));
+        let entries: Vec<u8> =
+            std::fs::read(journal.run_directory.join(ENTRIES_FILE_NAME)).unwrap();
+        journal.save().unwrap();
+        assert!(!journal.run_directory.join(ENTRIES_FILE_NAME).exists());
+        // entries which are already in journal.json are not added twice
+        std::fs::write(journal.run_directory.join(ENTRIES_FILE_NAME), entries).unwrap();
+        assert_eq!(
+            Journal::read(&journal.run_directory).unwrap().files.len(),
+            2
+        );
+    }
+
+    #[test]
+    fn journal_records_every_rule() {
+        let directory: TempDir = tempfile::tempdir().unwrap();
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .search("foo")
+            .replace("bar")
+            .ignore_case(true)
+            .rule(Rule {
+                map: vec![(String::from("a"), String::from("b"))],
+                map_file: Some(PathBuf::from("mapping.csv")),
+                ..Rule::default()
+            })
+            .rule(Rule {
+                script: Some(PathBuf::from("replace.rhai")),
+                ..Rule::new("(\\w+)", "")
+            })
+            .build();
+        let journal: Journal = Journal::create(directory.path(), &options).unwrap();
+        let read: Journal = Journal::read(&journal.run_directory).unwrap();
+        let plain = |search: &str, replace: &str| JournalRule {
+            search: String::from(search),
+            replace: String::from(replace),
+            flags: Vec::new(),
+            map_file: None,
+            script: None,
+        };
+        assert_eq!(
+            read.arguments.rules,
+            vec![
+                JournalRule {
+                    flags: vec![String::from("ignore-case")],
+                    ..plain("foo", "bar")
+                },
+                JournalRule {
+                    map_file: Some(PathBuf::from("mapping.csv")),
+                    ..plain("", "")
+                },
+                JournalRule {
+                    script: Some(PathBuf::from("replace.rhai")),
+                    ..plain("(\\w+)", "")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn prune_keeps_latest_runs() {
+        let directory: TempDir = tempfile::tempdir().unwrap();
+        let run_ids: Vec<String> = (0..3)
+            .map(|_| {
+                Journal::create(directory.path(), &ReplaceOptions::builder().build())
+                    .unwrap()
+                    .run_id
+            })
+            .collect();
+        let removed: Vec<Journal> = prune(directory.path(), 1).unwrap();
+        assert_eq!(removed.len(), 2);
+        let journals: Vec<Journal> = history(directory.path()).unwrap();
+        assert_eq!(journals.len(), 1);
+        assert_eq!(journals[0].run_id, run_ids[2]);
+        assert!(prune(directory.path(), 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn format_timestamp_utc() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "19700101T000000Z");
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(1_671_356_112)),
+            "20221218T093512Z"
+        );
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "20000229T000000Z"
+        );
+    }
+
+    #[test]
+    fn run_ids_are_timestamps() {
+        assert!(is_run_id("20221218T093512Z"));
+        assert!(is_run_id("20221218T093512Z-12"));
+        assert!(!is_run_id("../20221218T093512Z"));
+        assert!(!is_run_id("20221218T093512Z-"));
+        assert!(!is_run_id("20221218T093512Z-1/.."));
+        assert!(!is_run_id("2022121ßT093512Z"));
+        assert!(!is_run_id("20221218ßT93512Z"));
+        let directory: TempDir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            Journal::load(&directory.path().join("state"), Some("../state")),
+            Err(SarError::RunNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn hash_sha256() {
+        assert_eq!(
+            hash(b"positive\n"),
+            "fd5ef3399cc11279d81440b7036b7ea34e8b38f2ed1b4c83a43e32e08179f2f5"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use super::SarError;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Reads a mapping file of `old,new` pairs, one per line and without a header: comma separated,
+/// or tab separated if the file name ends with `.tsv`. Fields can be quoted like in any CSV file.
+/// Every problem found is reported in a single [`SarError::Mapping`], numbered by line. The pairs
+/// are meant for [`Rule::map`](super::Rule::map)
+pub fn load_mapping(path: &Path) -> Result<Vec<(String, String)>, SarError> {
+    let fail = |errors: Vec<String>| SarError::Mapping {
+        path: path.to_path_buf(),
+        errors,
+    };
+    let text: String =
+        std::fs::read_to_string(path).map_err(|error| fail(vec![error.to_string()]))?;
+    let delimiter: u8 = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("tsv") => b'\t',
+        _ => b',',
+    };
+    parse(&text, delimiter).map_err(fail)
+}
+
+fn parse(text: &str, delimiter: u8) -> Result<Vec<(String, String)>, Vec<String>> {
+    let mut reader: csv::Reader<&[u8]> = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(text.as_bytes());
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    // index in pairs and line of every key, to report duplicates
+    let mut firsts: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut errors: Vec<String> = Vec::new();
+    for record in reader.records() {
+        let record: csv::StringRecord = match record {
+            Ok(record) => record,
+            Err(error) => {
+                errors.push(error.to_string());
+                continue;
+            }
+        };
+        let line: u64 = record
+            .position()
+            .map(|position| position.line())
+            .unwrap_or(0);
+        let (key, value): (&str, &str) = match (record.len(), record.get(0), record.get(1)) {
+            (2, Some(key), Some(value)) => (key, value),
+            (1, Some(""), _) => continue, // blank line
+            (count, _, _) => {
+                errors.push(format!("line {}: expected 2 fields, found {}", line, count));
+                continue;
+            }
+        };
+        if key.is_empty() {
+            errors.push(format!("line {}: key cannot be blank", line));
+            continue;
+        }
+        match firsts.get(key) {
+            Some((index, first)) => {
+                let (_, first_value): &(String, String) = &pairs[*index];
+                // a repeated pair changes nothing
+                if first_value != value {
+                    errors.push(format!(
+                        "line {}: key \"{}\" is already mapped to \"{}\" on line {}",
+                        line, key, first_value, first
+                    ));
+                }
+            }
+            None => {
+                firsts.insert(key.to_string(), (pairs.len(), line as usize));
+                pairs.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+    if pairs.is_empty() && errors.is_empty() {
+        errors.push(String::from("no pairs found"));
+    }
+    if errors.is_empty() {
+        Ok(pairs)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::mapping::parse;
+
+    #[test]
+    fn parse_pairs_and_errors() {
+        let pairs: Vec<(String, String)> = parse("old,new\n\n\"a,b\",c\nold,new\n", b',').unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                (String::from("old"), String::from("new")),
+                (String::from("a,b"), String::from("c"))
+            ]
+        );
+        let errors: Vec<String> = parse("a\tb\nc\td\te\n\tf\na\tg\n", b'\t').unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "line 2: expected 2 fields, found 3",
+                "line 3: key cannot be blank",
+                "line 4: key \"a\" is already mapped to \"b\" on line 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,697 @@
+use super::case;
+use super::counter::Counters;
+use super::filter::FileFilter;
+use super::script::{Script, ScriptInput};
+use super::template::{CounterKind, Group, Template};
+use super::{build_regex, MatchSpan, ReplaceOptions, Rule, RuleOrder, SarError};
+use aho_corasick::{AhoCorasick, MatchKind};
+use regex::{Captures, Match, Regex};
+use regex_syntax::ast::{self, Ast, ClassSetItem};
+use regex_syntax::is_word_character;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Compiled rules of a single pass over a file, see [`build_matchers`]
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    rules: Vec<RuleMatcher>,
+}
+
+/// A match of a rule, see [`Matcher::find`]
+pub(crate) struct Found<'t> {
+    /// Position of the rule in its [`Matcher`]
+    rule: usize,
+    start: usize,
+    end: usize,
+    matched: &'t str,
+    /// Key of a [`Rule::map`] which matched
+    key: Option<usize>,
+    groups: Groups<'t>,
+}
+
+enum Groups<'t> {
+    /// Groups of a regex match, for the template of the rule
+    Captures(Captures<'t>),
+    /// Only the whole match, of a fixed string or a literal replace text
+    Whole,
+    /// Replacement of an identifier rename or a map key, not a template
+    Replacement(String),
+}
+
+/// Where a searched text is, the path and line number of a match are given to a replace script
+pub(crate) struct Location<'a> {
+    pub(crate) path: &'a Path,
+    /// Number of the first line of the text
+    pub(crate) line_number: usize,
+    /// Byte offset of every line in the text, starting with 0
+    pub(crate) starts: &'a [usize],
+}
+
+impl Location<'_> {
+    fn line_number(&self, offset: usize) -> usize {
+        self.line_number + self.starts.partition_point(|start| *start <= offset) - 1
+    }
+}
+
+/// A replacement which could not be expanded, e.g. a filter given a group it cannot read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ExpandError {
+    /// Byte offset of the match in the searched text
+    pub(crate) start: usize,
+    pub(crate) message: String,
+}
+
+#[derive(Debug, Clone)]
+struct RuleMatcher {
+    /// Index of the rule in [`ReplaceOptions::rules`]
+    index: usize,
+    search: Search,
+    /// Only a fixed string search or a map needs to check word boundaries, a regex has them built
+    /// in
+    word: bool,
+    literal_replace: bool,
+    preserve_case: bool,
+    template: Template,
+    /// Replacement of every group of an identifier rename, see [`build_identifier`]
+    variants: Vec<String>,
+    /// Run for every match instead of expanding the template, see [`Rule::script`]
+    script: Option<Arc<Script>>,
+    /// Files the rule applies to, `None` for all of them
+    files: Option<FileFilter>,
+}
+
+#[derive(Debug, Clone)]
+enum Search {
+    Regex(Regex),
+    /// Case sensitive search text matched literally, without a regex
+    Fixed(AhoCorasick),
+    /// Keys of a [`Rule::map`] in a single automaton, and the value of every key
+    Map(AhoCorasick, Vec<String>),
+}
+
+/// Compiles the rules of `options`, each as a regex or as a fixed string, see
+/// [`ReplaceOptionsBuilder::fixed_strings`](super::ReplaceOptionsBuilder::fixed_strings). Returns
+/// the passes over every file: one per rule with [`RuleOrder::Sequential`], a single one with
+/// [`RuleOrder::Simultaneous`]. Called by [`process_directory`](super::process_directory), can be
+/// used to validate the rules before a run
+pub fn build_matchers(options: &ReplaceOptions) -> Result<Vec<Matcher>, SarError> {
+    // validated here for a run, applied while walking the directory
+    FileFilter::build(options.include(), options.exclude(), &[])?;
+    let rules: Vec<RuleMatcher> = options
+        .rules()
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| build_rule(index, rule, options))
+        .collect::<Result<_, _>>()?;
+    Ok(match options.rule_order() {
+        RuleOrder::Sequential => rules
+            .into_iter()
+            .map(|rule| Matcher { rules: vec![rule] })
+            .collect(),
+        RuleOrder::Simultaneous => vec![Matcher { rules }],
+    })
+}
+
+fn build_rule(
+    index: usize,
+    rule: &Rule,
+    options: &ReplaceOptions,
+) -> Result<RuleMatcher, SarError> {
+    let mut variants: Vec<String> = Vec::new();
+    // case folding is left to the regex, the automaton only folds ASCII letters
+    let search: Search = if let Some((old, new)) = &rule.identifier {
+        let (regex, new_variants): (Regex, Vec<String>) = build_identifier(old, new)?;
+        variants = new_variants;
+        Search::Regex(regex)
+    } else if !rule.map.is_empty() {
+        build_map(rule)
+    } else if rule.fixed_strings && !is_case_insensitive(rule) {
+        Search::Fixed(
+            AhoCorasick::new([rule.search.as_str()])
+                .expect("a single search text is within the limits of the automaton"),
+        )
+    } else {
+        Search::Regex(build_regex(rule, options)?)
+    };
+    // the replacements of a map or an identifier rename are neither templates nor scripts
+    let script: Option<Arc<Script>> = match &rule.script {
+        Some(path) if variants.is_empty() && rule.map.is_empty() => {
+            Some(Arc::new(Script::load(path)?))
+        }
+        _ => None,
+    };
+    let template: Template = if rule.literal_replace || !variants.is_empty() || !rule.map.is_empty()
+    {
+        Template::literal(&rule.replace)
+    } else {
+        let groups: Vec<&str> = match &search {
+            Search::Regex(regex) => regex.capture_names().flatten().collect(),
+            Search::Fixed(_) | Search::Map(..) => Vec::new(),
+        };
+        Template::parse(&rule.replace, &groups, rule.case_spans)?
+    };
+    let files: FileFilter = FileFilter::build(&rule.include, &rule.exclude, &rule.ignored_dirs)?;
+    Ok(RuleMatcher {
+        index,
+        word: rule.word_regexp && !matches!(search, Search::Regex(_)),
+        search,
+        literal_replace: rule.literal_replace,
+        preserve_case: rule.preserve_case,
+        template,
+        variants,
+        script,
+        files: if files.is_empty() { None } else { Some(files) },
+    })
+}
+
+/// A regex of every case style of `old`, one group per style, and the same style of `new` for every
+/// group
+fn build_identifier(old: &str, new: &str) -> Result<(Regex, Vec<String>), SarError> {
+    for name in [old, new] {
+        if !case::is_identifier_name(name) {
+            return Err(SarError::InvalidIdentifier {
+                name: name.to_string(),
+            });
+        }
+    }
+    let mut olds: Vec<String> = Vec::new();
+    let mut news: Vec<String> = Vec::new();
+    for (old, new) in case::identifier_variants(old)
+        .into_iter()
+        .zip(case::identifier_variants(new))
+    {
+        // e.g. camel and snake case of a single word
+        if !olds.contains(&old) {
+            olds.push(old);
+            news.push(new);
+        }
+    }
+    let pattern: String = olds
+        .iter()
+        .map(|old| format!("({})", regex::escape(old)))
+        .collect::<Vec<String>>()
+        .join("|");
+    let regex: Regex =
+        Regex::new(&pattern).map_err(|error| SarError::invalid_pattern(&pattern, error))?;
+    Ok((regex, news))
+}
+
+/// A map is too large for a regex of its keys. Its case folding is limited to ASCII letters
+fn build_map(rule: &Rule) -> Search {
+    // with whole words a shorter key can match where the longest one is not a word, so every
+    // match is found and the leftmost longest whole word is picked in `find`
+    let match_kind: MatchKind = if rule.word_regexp {
+        MatchKind::Standard
+    } else {
+        MatchKind::LeftmostLongest
+    };
+    let automaton: AhoCorasick = AhoCorasick::builder()
+        .match_kind(match_kind)
+        .ascii_case_insensitive(is_case_insensitive(rule))
+        .build(rule.map.iter().map(|(key, _)| key))
+        .expect("the keys of a map are within the limits of the automaton");
+    let values: Vec<String> = rule.map.iter().map(|(_, value)| value.clone()).collect();
+    Search::Map(automaton, values)
+}
+
+/// Ignore case or preserve case, or smart case and the search text (every key of a map) has no
+/// uppercase letter
+pub(crate) fn is_case_insensitive(rule: &Rule) -> bool {
+    if rule.ignore_case || rule.preserve_case || !rule.smart_case {
+        return rule.ignore_case || rule.preserve_case;
+    }
+    if rule.map.is_empty() {
+        !has_uppercase(&rule.search, rule.fixed_strings)
+    } else {
+        !rule.map.iter().any(|(key, _)| has_uppercase(key, true))
+    }
+}
+
+/// True if a literal character of the search text is uppercase, escapes like `\S` don't count
+fn has_uppercase(search: &str, fixed_strings: bool) -> bool {
+    if fixed_strings {
+        return search.chars().any(char::is_uppercase);
+    }
+    match ast::parse::Parser::new().parse(search) {
+        Ok(ast) => ast::visit(&ast, UppercaseVisitor(false)).unwrap_or(false),
+        Err(_) => false, // reported when the regex is built
+    }
+}
+
+struct UppercaseVisitor(bool);
+
+impl ast::Visitor for UppercaseVisitor {
+    type Output = bool;
+    type Err = ();
+
+    fn finish(self) -> Result<bool, ()> {
+        Ok(self.0)
+    }
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), ()> {
+        if let Ast::Literal(literal) = ast {
+            self.0 |= literal.c.is_uppercase();
+        }
+        Ok(())
+    }
+
+    fn visit_class_set_item_pre(&mut self, item: &ClassSetItem) -> Result<(), ()> {
+        match item {
+            ClassSetItem::Literal(literal) => self.0 |= literal.c.is_uppercase(),
+            ClassSetItem::Range(range) => {
+                self.0 |= range.start.c.is_uppercase() || range.end.c.is_uppercase()
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+impl Matcher {
+    #[cfg(test)]
+    pub(crate) fn from_regex(regex: Regex, replace: &str) -> Matcher {
+        Matcher {
+            rules: vec![RuleMatcher {
+                index: 0,
+                search: Search::Regex(regex),
+                word: false,
+                literal_replace: false,
+                preserve_case: false,
+                template: Template::parse(replace, &[], false).expect("valid replace text"),
+                variants: Vec::new(),
+                script: None,
+                files: None,
+            }],
+        }
+    }
+
+    /// True if no rule applies
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The rules which apply to the file at `relative_path`, see [`Rule::include`]
+    pub(crate) fn for_file(&self, relative_path: &Path) -> Cow<'_, Matcher> {
+        let applies = |rule: &RuleMatcher| {
+            rule.files
+                .as_ref()
+                .map(|files| files.is_match(relative_path))
+                .unwrap_or(true)
+        };
+        if self.rules.iter().all(applies) {
+            return Cow::Borrowed(self);
+        }
+        Cow::Owned(Matcher {
+            rules: self
+                .rules
+                .iter()
+                .filter(|rule| applies(rule))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Every match in `line`. Of overlapping matches of several rules only the one starting first
+    /// is kept, then the one of the rule given first. See [`Matcher::expand`] for the replacement
+    pub(crate) fn find<'t>(&self, line: &'t str) -> Vec<Found<'t>> {
+        match self.rules.as_slice() {
+            [] => return Vec::new(),
+            [rule] => return rule.find(0, line),
+            _ => (),
+        }
+        let mut found: Vec<Found<'t>> = self
+            .rules
+            .iter()
+            .enumerate()
+            .flat_map(|(position, rule)| rule.find(position, line))
+            .collect();
+        found.sort_by_key(|found| (found.start, found.rule));
+        let mut end: Option<usize> = None;
+        found.retain(|found| {
+            let keep: bool = end.map(|end| found.start >= end).unwrap_or(true);
+            if keep {
+                end = Some(found.end.max(found.start + 1));
+            }
+            keep
+        });
+        found
+    }
+
+    /// The replacement of a match, numbered after the matches `counters` counted so far. `None` if
+    /// a replace script leaves the match alone. Fails if the replacement cannot be expanded
+    pub(crate) fn expand(
+        &self,
+        found: Found<'_>,
+        counters: &Counters,
+        location: &Location<'_>,
+    ) -> Result<Option<MatchSpan>, ExpandError> {
+        let rule: &RuleMatcher = &self.rules[found.rule];
+        let fail = |message: String| ExpandError {
+            start: found.start,
+            message,
+        };
+        if let Some(script) = &rule.script {
+            let input: ScriptInput<'_> = match (&found.groups, &rule.search) {
+                (Groups::Captures(captures), Search::Regex(regex)) => ScriptInput {
+                    captures: captures
+                        .iter()
+                        .map(|group| group.map(|group| group.as_str()))
+                        .collect(),
+                    groups: regex
+                        .capture_names()
+                        .enumerate()
+                        .filter_map(|(index, name)| Some((name?, captures.get(index)?.as_str())))
+                        .collect(),
+                    path: location.path,
+                    line: location.line_number(found.start),
+                },
+                _ => ScriptInput {
+                    captures: vec![Some(found.matched)],
+                    groups: Vec::new(),
+                    path: location.path,
+                    line: location.line_number(found.start),
+                },
+            };
+            return Ok(script
+                .run(input)
+                .map_err(fail)?
+                .map(|replacement| rule.span(&found, replacement)));
+        }
+        let counted = |kind: CounterKind| counters.counted(rule.index, kind);
+        let replacement: String = match &found.groups {
+            Groups::Replacement(replacement) => Ok(replacement.clone()),
+            Groups::Captures(captures) => rule.template.expand(
+                |group| {
+                    match group {
+                        Group::Index(index) => captures.get(*index),
+                        Group::Name(name) => captures.name(name),
+                    }
+                    .map(|group| group.as_str())
+                },
+                counted,
+            ),
+            // a fixed string has no groups but the whole match
+            Groups::Whole => rule.template.expand(
+                |group| (*group == Group::Index(0)).then_some(found.matched),
+                counted,
+            ),
+        }
+        .map_err(fail)?;
+        Ok(Some(rule.span(&found, replacement)))
+    }
+
+    /// Numbers an accepted match in `counters`, if its replacement uses a counter
+    pub(crate) fn count(&self, span: &MatchSpan, counters: &mut Counters) {
+        if let Some(rule) = self.rules.iter().find(|rule| rule.index == span.rule) {
+            for kind in [CounterKind::Match, CounterKind::File] {
+                if rule.template.uses(kind) {
+                    counters.count(rule.index, kind);
+                }
+            }
+        }
+    }
+}
+
+impl RuleMatcher {
+    fn span(&self, found: &Found<'_>, replacement: String) -> MatchSpan {
+        MatchSpan {
+            start: found.start,
+            end: found.end,
+            replacement: if self.preserve_case {
+                case::preserve_case(found.matched, &replacement)
+            } else {
+                replacement
+            },
+            rule: self.index,
+            key: found.key,
+        }
+    }
+
+    /// Every match in `line`, `position` is the position of the rule in its [`Matcher`]
+    fn find<'t>(&self, position: usize, line: &'t str) -> Vec<Found<'t>> {
+        let found = |start: usize, end: usize, groups: Groups<'t>| Found {
+            rule: position,
+            start,
+            end,
+            matched: &line[start..end],
+            key: None,
+            groups,
+        };
+        let is_word = |start: usize, end: usize| {
+            !self.word || (is_word_boundary(line, start) && is_word_boundary(line, end))
+        };
+        match &self.search {
+            Search::Regex(regex) if !self.variants.is_empty() => regex
+                .captures_iter(line)
+                .filter_map(|captures: Captures| {
+                    let whole: Match<'_> = captures.get(0).expect("group 0 is always matched");
+                    if !case::is_identifier_boundary(line, whole.start())
+                        || !case::is_identifier_boundary(line, whole.end())
+                    {
+                        return None;
+                    }
+                    let group: usize = (1..captures.len())
+                        .find(|group| captures.get(*group).is_some())
+                        .expect("one group of every variant matched");
+                    let replacement: String = self.variants[group - 1].clone();
+                    Some(found(
+                        whole.start(),
+                        whole.end(),
+                        Groups::Replacement(replacement),
+                    ))
+                })
+                .collect(),
+            Search::Regex(regex) if self.literal_replace => regex
+                .find_iter(line)
+                .map(|whole| found(whole.start(), whole.end(), Groups::Whole))
+                .collect(),
+            Search::Regex(regex) => regex
+                .captures_iter(line)
+                .map(|captures: Captures<'t>| {
+                    let whole: Match<'_> = captures.get(0).expect("group 0 is always matched");
+                    found(whole.start(), whole.end(), Groups::Captures(captures))
+                })
+                .collect(),
+            Search::Fixed(automaton) => automaton
+                .find_iter(line)
+                .filter(|whole| is_word(whole.start(), whole.end()))
+                .map(|whole| found(whole.start(), whole.end(), Groups::Whole))
+                .collect(),
+            Search::Map(automaton, values) => {
+                let mut keys: Vec<aho_corasick::Match> = if self.word {
+                    automaton
+                        .find_overlapping_iter(line)
+                        .filter(|key| is_word(key.start(), key.end()))
+                        .collect()
+                } else {
+                    automaton.find_iter(line).collect()
+                };
+                if self.word {
+                    // leftmost longest of the whole words, the key given first on a tie
+                    keys.sort_by_key(|key| (key.start(), Reverse(key.end()), key.pattern()));
+                    let mut end: usize = 0;
+                    keys.retain(|key| {
+                        let keep: bool = key.start() >= end;
+                        if keep {
+                            end = key.end();
+                        }
+                        keep
+                    });
+                }
+                keys.into_iter()
+                    .map(|key| Found {
+                        key: Some(key.pattern().as_usize()),
+                        ..found(
+                            key.start(),
+                            key.end(),
+                            Groups::Replacement(values[key.pattern().as_usize()].clone()),
+                        )
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Unicode aware `\b` at byte offset `index` of `text`
+fn is_word_boundary(text: &str, index: usize) -> bool {
+    let before: bool = text[..index]
+        .chars()
+        .next_back()
+        .map(is_word_character)
+        .unwrap_or(false);
+    let after: bool = text[index..]
+        .chars()
+        .next()
+        .map(is_word_character)
+        .unwrap_or(false);
+    before != after
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::counter::Counters;
+    use crate::core::matcher::{build_matchers, has_uppercase, Location, Matcher};
+    use crate::core::{MatchSpan, ReplaceOptions, Rule, RuleOrder};
+    use std::path::Path;
+
+    fn spans(matcher: &Matcher, line: &str) -> Vec<MatchSpan> {
+        let counters: Counters = Counters::new(&ReplaceOptions::builder().build());
+        let location: Location = Location {
+            path: Path::new("file.txt"),
+            line_number: 1,
+            starts: &[0],
+        };
+        matcher
+            .find(line)
+            .into_iter()
+            .filter_map(|found| matcher.expand(found, &counters, &location).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn fixed_strings_match_literally() {
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .search("a.b(c)")
+            .replace("[$0]")
+            .fixed_strings(true)
+            .build();
+        let matcher: Matcher = build_matchers(&options).unwrap().remove(0);
+        let spans: Vec<MatchSpan> = spans(&matcher, "axb(c) a.b(c)");
+        assert_eq!(
+            spans,
+            vec![MatchSpan {
+                start: 7,
+                end: 13,
+                replacement: String::from("[a.b(c)]"),
+                rule: 0,
+                key: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn literal_replace_does_not_expand() {
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .search(r"(?P<dir>\w+)/bin")
+            .replace("$HOME/${dir}")
+            .literal_replace(true)
+            .build();
+        let matcher: Matcher = build_matchers(&options).unwrap().remove(0);
+        let spans: Vec<MatchSpan> = spans(&matcher, "PATH=usr/bin");
+        assert_eq!(spans[0].replacement, "$HOME/${dir}");
+    }
+
+    fn find(options: ReplaceOptions, line: &str) -> Vec<(usize, usize)> {
+        spans(&build_matchers(&options).unwrap()[0], line)
+            .iter()
+            .map(|span| (span.start, span.end))
+            .collect()
+    }
+
+    #[test]
+    fn word_regexp_unicode_boundaries() {
+        let line: &str = "número números _número número";
+        for fixed_strings in [false, true] {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .search("número")
+                .fixed_strings(fixed_strings)
+                .word_regexp(true)
+                .build();
+            assert_eq!(find(options, line), vec![(0, 7), (26, 33)]);
+        }
+    }
+
+    #[test]
+    fn ignore_case_unicode_fixed_strings() {
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .search("NÚMERO")
+            .fixed_strings(true)
+            .ignore_case(true)
+            .word_regexp(true)
+            .build();
+        assert_eq!(find(options, "número a.b NúmeroS"), vec![(0, 7)]);
+    }
+
+    #[test]
+    fn smart_case_uppercase_literals() {
+        assert!(!has_uppercase(r"\S+\W[a-z]\p{Lu}", false));
+        assert!(has_uppercase(r"\S+Foo", false));
+        assert!(has_uppercase(r"[A-Z]", false));
+        assert!(has_uppercase(r"\S", true));
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .search(r"foo\.bar")
+            .smart_case(true)
+            .build();
+        assert_eq!(find(options, "FOO.BAR foo.bar"), vec![(0, 7), (8, 15)]);
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .search(r"Foo\.bar")
+            .smart_case(true)
+            .build();
+        assert_eq!(find(options, "FOO.BAR Foo.bar"), vec![(8, 15)]);
+    }
+
+    #[test]
+    fn map_leftmost_longest_keys() {
+        let map: Vec<(String, String)> = [("foo", "1"), ("foo bar", "2"), ("Bar", "3")]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let find = |rule: Rule, line: &str| -> Vec<(usize, usize, String, Option<usize>)> {
+            let options: ReplaceOptions = ReplaceOptions::builder().rule(rule).build();
+            spans(&build_matchers(&options).unwrap()[0], line)
+                .into_iter()
+                .map(|span| (span.start, span.end, span.replacement, span.key))
+                .collect()
+        };
+        let rule: Rule = Rule {
+            map: map.clone(),
+            ..Rule::default()
+        };
+        assert_eq!(
+            find(rule, "foo bar foo barn"),
+            vec![
+                (0, 7, String::from("2"), Some(1)),
+                (8, 15, String::from("2"), Some(1))
+            ]
+        );
+        // the longest key is not a whole word, the shorter one is
+        let rule: Rule = Rule {
+            map,
+            word_regexp: true,
+            ignore_case: true,
+            ..Rule::default()
+        };
+        assert_eq!(
+            find(rule, "foo barn bar"),
+            vec![
+                (0, 3, String::from("1"), Some(0)),
+                (9, 12, String::from("3"), Some(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn simultaneous_rules_earliest_match_wins() {
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .rule(Rule::new("bc", "1"))
+            .rule(Rule::new("abc", "2"))
+            .rule(Rule::new("cd|e", "3"))
+            .rule_order(RuleOrder::Simultaneous)
+            .build();
+        let matchers: Vec<Matcher> = build_matchers(&options).unwrap();
+        assert_eq!(matchers.len(), 1);
+        let spans: Vec<(usize, usize, usize)> = spans(&matchers[0], "abcde bcd")
+            .iter()
+            .map(|span| (span.start, span.end, span.rule))
+            .collect();
+        assert_eq!(spans, vec![(0, 3, 1), (4, 5, 2), (6, 8, 0)]);
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .search("a")
+            .rule(Rule::new("b", ""))
+            .build();
+        assert_eq!(build_matchers(&options).unwrap().len(), 2);
+    }
+}
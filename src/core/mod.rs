@@ -1,54 +1,198 @@
-use regex::Regex;
+mod atomic;
+mod backup;
+mod case;
+mod counter;
+mod diff;
+mod error;
+mod filter;
+mod interactive;
+mod journal;
+mod mapping;
+mod matcher;
+mod options;
+mod report;
+mod review;
+mod rules_file;
+mod script;
+mod template;
+mod write;
+
+pub use backup::Backup;
+pub use diff::unified_diff;
+pub use error::{PatternSpan, SarError};
+pub use interactive::{Decision, MatchCandidate};
+pub use journal::{
+    default_state_dir, history, prune, undo, Journal, JournalArguments, JournalEntry, JournalRule,
+    UndoFile, UndoOutcome, UndoReport,
+};
+pub use mapping::load_mapping;
+pub use matcher::{build_matchers, Matcher};
+pub use options::{CounterScope, ReplaceOptions, ReplaceOptionsBuilder, Rule, RuleOrder};
+pub use report::{FileOutcome, FileReport, LineMatch, MatchSpan, Report};
+pub use review::{apply, review, Hunk, Review, ReviewFile};
+pub use rules_file::{
+    load_rules, run_examples, ExampleResult, RuleExample, RulesFile, RULES_FILE_VERSION,
+};
+
+use counter::Counters;
+use filter::FileFilter;
+use interactive::Selector;
+use matcher::{ExpandError, Location};
+use regex::{Regex, RegexBuilder};
 use std::borrow::Cow;
-use std::fs::{remove_file, File};
-use std::io::{BufRead, BufReader, LineWriter, Result, Write};
-use std::path::Path;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::path::{Path, PathBuf};
 use walkdir::{DirEntry, WalkDir};
 
-pub static TOTAL_FILES_TO_BE_EDITED: AtomicUsize = AtomicUsize::new(0);
-pub static TOTAL_FILES_EDITED_OK: AtomicUsize = AtomicUsize::new(0);
-pub static TOTAL_FILES_PROCESS_ERROR: AtomicUsize = AtomicUsize::new(0);
-pub static TOTAL_DIR_READ_ERROR: AtomicUsize = AtomicUsize::new(0);
-
-pub fn process_directory(
-    directory: &Path,
-    file_extensions: &Vec<String>,
-    ignored_dirs: &Vec<String>,
-    search: &str,
-    replace: &str,
-    dry_run: &bool,
-) {
-    let regex: Regex = Regex::new(search).unwrap();
-    WalkDir::new(directory)
+/// Compiles the search text of `rule` as a regex, with the case and whole word options applied.
+/// A fixed string search text is escaped, [`build_matchers`] searches it without a regex if it can.
+pub fn build_regex(rule: &Rule, options: &ReplaceOptions) -> Result<Regex, SarError> {
+    let search: &str = rule.search.as_str();
+    let mut pattern: String = if rule.fixed_strings {
+        regex::escape(search)
+    } else {
+        search.to_string()
+    };
+    if rule.word_regexp {
+        if !rule.fixed_strings {
+            // validated on its own, wrapped in a group it would accept e.g. `a)(b`
+            Regex::new(search).map_err(|error| SarError::invalid_pattern(search, error))?;
+        }
+        pattern = format!(r"\b(?:{})\b", pattern);
+    }
+    RegexBuilder::new(&pattern)
+        .case_insensitive(matcher::is_case_insensitive(rule))
+        .multi_line(options.multiline())
+        .build()
+        .map_err(|error| SarError::invalid_pattern(search, error))
+}
+
+pub fn process_directory(options: &ReplaceOptions) -> Result<Report, SarError> {
+    run(options, Selector::all())
+}
+
+/// Like [`process_directory`], but every match is offered to `confirm` first and only the accepted
+/// matches are replaced. Files are walked one after the other, a [`Decision::Quit`] stops the run
+pub fn process_directory_interactive<F>(
+    options: &ReplaceOptions,
+    mut confirm: F,
+) -> Result<Report, SarError>
+where
+    F: FnMut(&MatchCandidate<'_>) -> Decision,
+{
+    run(options, Selector::confirming(&mut confirm))
+}
+
+fn run(options: &ReplaceOptions, mut selector: Selector<'_>) -> Result<Report, SarError> {
+    let matchers: Vec<Matcher> = build_matchers(options)?;
+    let mut counters: Counters = Counters::new(options);
+    let mut report: Report = Report::default();
+    let mut journal: Option<Journal> = match options.journal_dir() {
+        Some(journal_dir) if !options.dry_run() => Some(Journal::create(journal_dir, options)?),
+        _ => None,
+    };
+    if options.atomic() && !options.dry_run() {
+        let mut file_paths: Vec<PathBuf> = Vec::new();
+        report.dir_errors = walk_directory(options, |file_path| {
+            file_paths.push(file_path.to_path_buf())
+        })?;
+        atomic::process_files(
+            &file_paths,
+            &matchers,
+            options,
+            &mut selector,
+            &mut counters,
+            journal.as_mut(),
+            &mut report,
+        );
+    } else {
+        let mut files: Vec<FileReport> = Vec::new();
+        report.dir_errors = walk_directory(options, |file_path| {
+            if selector.is_quit() {
+                return;
+            }
+            if let Some(file_report) = process_file(
+                file_path,
+                &matchers,
+                options,
+                &mut selector,
+                &mut counters,
+                journal.as_mut(),
+            ) {
+                files.push(file_report);
+            }
+        })?;
+        report.files = files;
+    }
+    let key_matches: Vec<Vec<usize>> = report.key_matches();
+    for (index, rule) in options.rules().iter().enumerate() {
+        for (key_index, (key, _)) in rule.map.iter().enumerate() {
+            let matches: usize = key_matches
+                .get(index)
+                .and_then(|matches| matches.get(key_index))
+                .copied()
+                .unwrap_or(0);
+            if matches == 0 {
+                report.unmatched_keys.push((index, key.clone()));
+            }
+        }
+    }
+    if let Some(journal) = journal {
+        if journal.files.is_empty() {
+            journal.discard();
+        } else {
+            // if saving fails the entries file is still there, and is read along with journal.json
+            let _ = journal.save();
+            report.run_id = Some(journal.run_id);
+        }
+    }
+    Ok(report)
+}
+
+/// Calls `process` for every file to be searched, sorted by name within every directory so runs
+/// are deterministic, returns the walk errors
+fn walk_directory<F: FnMut(&Path)>(
+    options: &ReplaceOptions,
+    mut process: F,
+) -> Result<Vec<SarError>, SarError> {
+    let files: FileFilter = FileFilter::build(options.include(), options.exclude(), &[])?;
+    let mut dir_errors: Vec<SarError> = Vec::new();
+    // links are not followed, so the canonical path of a directory below is this one plus its
+    // relative path
+    let root: Option<PathBuf> = fs::canonicalize(options.directory()).ok();
+    WalkDir::new(options.directory())
+        .sort_by_file_name()
         .into_iter()
-        .filter_entry(|e| !is_directory_ignored(e, ignored_dirs))
+        .filter_entry(|e| {
+            !is_directory_ignored(e, options.ignored_dirs())
+                && !is_sar_state(e, root.as_deref(), options)
+        })
         .for_each(|entry| match entry {
             Ok(entry) => {
                 if entry.file_type().is_file()
-                    && is_matching_file(entry.file_name().to_str(), file_extensions)
+                    && is_matching_file(entry.file_name().to_str(), options.file_extensions())
+                    && files.is_match(relative_path(entry.path(), options))
                 {
-                    match process_file(&entry.path(), &regex, replace, dry_run) {
-                        Ok(()) => (),
-                        Err(error) => {
-                            eprintln!(
-                                "Error processing file: {} -- {}",
-                                entry.path().display(),
-                                error
-                            );
-                            TOTAL_FILES_PROCESS_ERROR.fetch_add(1, Ordering::Relaxed);
-                        }
-                    }
+                    process(entry.path());
                 }
             }
-            Err(error) => {
-                eprintln!("Error: {}", error);
-                TOTAL_DIR_READ_ERROR.fetch_add(1, Ordering::Relaxed);
-            }
+            Err(error) => dir_errors.push(SarError::Walk {
+                path: error.path().map(Path::to_path_buf),
+                source: error,
+            }),
         });
+    Ok(dir_errors)
+}
+
+/// Path of a file relative to the searched directory, matched against include and exclude globs
+fn relative_path<'a>(file_path: &'a Path, options: &ReplaceOptions) -> &'a Path {
+    file_path
+        .strip_prefix(options.directory())
+        .unwrap_or(file_path)
 }
 
-fn is_directory_ignored(entry: &DirEntry, ignored_dirs: &Vec<String>) -> bool {
+fn is_directory_ignored(entry: &DirEntry, ignored_dirs: &[String]) -> bool {
     entry.file_type().is_dir()
         && entry
             .file_name()
@@ -57,154 +201,560 @@ fn is_directory_ignored(entry: &DirEntry, ignored_dirs: &Vec<String>) -> bool {
             .unwrap_or(false)
 }
 
-fn is_matching_file(entry: Option<&str>, file_extensions: &Vec<String>) -> bool {
+/// True for backups and undo journals, which must never be searched. `root` is the canonical path
+/// of the searched directory
+fn is_sar_state(entry: &DirEntry, root: Option<&Path>, options: &ReplaceOptions) -> bool {
+    if entry.file_type().is_dir() {
+        root.map(|root| {
+            let path: PathBuf = root.join(relative_path(entry.path(), options));
+            options.state_dirs().contains(&path)
+        })
+        .unwrap_or(false)
+    } else {
+        options
+            .backup()
+            .map(|backup| backup.is_backup_file(entry.path()))
+            .unwrap_or(false)
+    }
+}
+
+fn is_matching_file(entry: Option<&str>, file_extensions: &[String]) -> bool {
     entry
         .map(|s| {
-            if file_extensions.len() == 0 {
-                return true;
+            if file_extensions.is_empty() {
+                true
             } else {
-                for file_extension in file_extensions {
-                    if s.ends_with(file_extension) {
-                        return true;
-                    }
-                }
-                return false;
+                file_extensions
+                    .iter()
+                    .any(|file_extension| s.ends_with(file_extension))
             }
         })
         .unwrap_or(false)
 }
 
-fn process_file(file_path: &Path, regex: &Regex, replace: &str, dry_run: &bool) -> Result<()> {
-    let mut lines: Vec<String> = Vec::new();
-    let mut found_and_replaced: bool = false;
-    let file: File = File::open(file_path)?;
-    let mut reader: BufReader<File> = BufReader::new(file);
-    loop {
-        let mut line: String = String::new();
-        let len: usize = reader.read_line(&mut line)?;
-        if len == 0 {
-            break;
+fn process_file(
+    file_path: &Path,
+    matchers: &[Matcher],
+    options: &ReplaceOptions,
+    selector: &mut Selector<'_>,
+    counters: &mut Counters,
+    journal: Option<&mut Journal>,
+) -> Option<FileReport> {
+    let replaced: Replaced =
+        match read_and_replace(file_path, matchers, options, selector, counters) {
+            Ok(Some(replaced)) => replaced,
+            Ok(None) => return None,
+            Err(error) => return Some(FileReport::failed(file_path, 0, error)),
+        };
+    let backup_path: Option<PathBuf> = backup_path(file_path, options);
+    let (outcome, backup): (FileOutcome, Option<PathBuf>) = if options.dry_run() {
+        (FileOutcome::Found, None)
+    } else {
+        match write::stage_file(
+            replaced.content.as_bytes(),
+            file_path,
+            options.preserve_mtime(),
+        )
+        .map_err(|error| SarError::Write {
+            path: file_path.to_path_buf(),
+            source: error,
+        })
+        .and_then(|staged| {
+            commit_file(
+                file_path,
+                &replaced.content,
+                staged,
+                backup_path.as_deref(),
+                journal,
+            )
+        }) {
+            Ok(backup) => (FileOutcome::Edited, backup),
+            Err(error) => (FileOutcome::Failed(error), None),
+        }
+    };
+    Some(FileReport {
+        path: file_path.to_path_buf(),
+        matches: replaced.matches,
+        outcome,
+        backup,
+        diff: file_diff(file_path, &replaced, options),
+        rule_matches: replaced.rule_matches,
+        key_matches: replaced.key_matches,
+        lines: replaced.lines,
+    })
+}
+
+/// Original and new content of a file where the search text was found
+struct Replaced {
+    original: String,
+    content: String,
+    matches: usize,
+    rule_matches: Vec<usize>,
+    key_matches: Vec<Vec<usize>>,
+    /// Matching lines, only collected for a preview
+    lines: Vec<LineMatch>,
+}
+
+/// Returns `None` if the search text was not found in the file, or no match was accepted. The
+/// numbers of a file which fails are given to the next files
+fn read_and_replace(
+    file_path: &Path,
+    matchers: &[Matcher],
+    options: &ReplaceOptions,
+    selector: &mut Selector<'_>,
+    counters: &mut Counters,
+) -> Result<Option<Replaced>, SarError> {
+    let lines: Vec<String> = read_lines(file_path)?;
+    let original: String = lines.concat();
+    selector.start_file();
+    counters.start_file();
+    let counted: Counters = counters.clone();
+    let (content, line_matches): (String, Vec<LineMatch>) =
+        replace_passes(file_path, lines, matchers, options, counters, |candidate| {
+            selector.accept(candidate)
+        })
+        .map_err(|error| {
+            *counters = counted;
+            error
+        })?;
+    let mut rule_matches: Vec<usize> = vec![0; options.rules().len()];
+    for span in line_matches.iter().flat_map(|line| &line.spans) {
+        rule_matches[span.rule] += 1;
+    }
+    let matches: usize = rule_matches.iter().sum();
+    if matches == 0 {
+        return Ok(None);
+    }
+    let mut key_matches: Vec<Vec<usize>> = options
+        .rules()
+        .iter()
+        .map(|rule| vec![0; rule.map.len()])
+        .collect();
+    for span in line_matches.iter().flat_map(|line| &line.spans) {
+        if let Some(key) = span.key {
+            key_matches[span.rule][key] += 1;
+        }
+    }
+    Ok(Some(Replaced {
+        content,
+        original,
+        matches,
+        rule_matches,
+        key_matches,
+        lines: if options.preview() {
+            line_matches
         } else {
+            Vec::new()
+        },
+    }))
+}
+
+/// Runs [`replace_lines`] once for every pass of `matchers`, each pass over the content left by
+/// the pass before it. The line numbers and lines of a match are the ones its pass saw
+fn replace_passes<F: FnMut(&MatchCandidate<'_>) -> bool>(
+    file_path: &Path,
+    lines: Vec<String>,
+    matchers: &[Matcher],
+    options: &ReplaceOptions,
+    counters: &mut Counters,
+    mut accept: F,
+) -> Result<(String, Vec<LineMatch>), SarError> {
+    let mut lines: Vec<String> = lines;
+    // content left by the latest pass which applied to the file
+    let mut content: Option<String> = None;
+    let mut line_matches: Vec<LineMatch> = Vec::new();
+    let relative_path: &Path = relative_path(file_path, options);
+    for matcher in matchers {
+        let matcher: Cow<'_, Matcher> = matcher.for_file(relative_path);
+        if matcher.is_empty() {
+            continue;
+        }
+        if let Some(content) = content.take() {
+            lines = content.split_inclusive('\n').map(String::from).collect();
+        }
+        let (replaced, found): (String, Vec<LineMatch>) =
+            replace_lines(file_path, &lines, &matcher, options, counters, &mut accept)?;
+        content = Some(replaced);
+        line_matches.extend(found);
+    }
+    Ok((content.unwrap_or_else(|| lines.concat()), line_matches))
+}
+
+/// Replaces every accepted match in `lines`, line by line or in the whole file with
+/// [`ReplaceOptions::multiline`]. Returns the new content and the accepted matches grouped by the
+/// lines they are on. Fails on the first match whose replacement cannot be expanded
+fn replace_lines<F: FnMut(&MatchCandidate<'_>) -> bool>(
+    file_path: &Path,
+    lines: &[String],
+    matcher: &Matcher,
+    options: &ReplaceOptions,
+    counters: &mut Counters,
+    mut accept: F,
+) -> Result<(String, Vec<LineMatch>), SarError> {
+    let replace_error = |line: usize, error: ExpandError| SarError::Replace {
+        path: file_path.to_path_buf(),
+        line,
+        message: error.message,
+    };
+    let mut line_matches: Vec<LineMatch> = Vec::new();
+    if !options.multiline() {
+        let mut content: String = String::new();
+        for (index, line) in lines.iter().enumerate() {
+            let mut spans: Vec<MatchSpan> = Vec::new();
+            let location: Location<'_> = Location {
+                path: file_path,
+                line_number: index + 1,
+                starts: &[0],
+            };
             let result: Cow<'_, str> =
-                search_and_replace(&line, regex, replace, &mut found_and_replaced);
-            match result {
-                Cow::Borrowed(_) => lines.push(line), // regex not matching, no replacement
-                Cow::Owned(val) => lines.push(val.to_owned()), // regex matching, replaced
+                search_and_replace(line, matcher, &location, counters, &mut spans, |span| {
+                    accept(&MatchCandidate {
+                        path: file_path,
+                        line_number: index + 1,
+                        end_line_number: index + 1,
+                        lines,
+                        span,
+                    })
+                })
+                .map_err(|error| replace_error(index + 1, error))?;
+            content.push_str(&result);
+            if !spans.is_empty() {
+                line_matches.push(LineMatch {
+                    line_number: index + 1,
+                    end_line_number: index + 1,
+                    line: line.clone(),
+                    spans,
+                });
             }
         }
+        return Ok((content, line_matches));
     }
-    if found_and_replaced {
-        println!("Search text found in file: {}", file_path.display());
-        TOTAL_FILES_TO_BE_EDITED.fetch_add(1, Ordering::Relaxed);
-        if !dry_run {
-            remove_file(file_path)?;
-            write_file(lines, file_path)?;
-            println!("Successfully edited file: {}", file_path.display());
-            TOTAL_FILES_EDITED_OK.fetch_add(1, Ordering::Relaxed);
-        }
+    let buffer: String = lines.concat();
+    if buffer.is_empty() {
+        return Ok((buffer, line_matches));
+    }
+    // byte offset of every line in the buffer
+    let starts: Vec<usize> = lines
+        .iter()
+        .scan(0, |start, line| {
+            let line_start: usize = *start;
+            *start += line.len();
+            Some(line_start)
+        })
+        .collect();
+    let line_index = |offset: usize| starts.partition_point(|start| *start <= offset) - 1;
+    // a match ending right after a line ending does not reach into the next line
+    let line_range = |span: &MatchSpan| {
+        (
+            line_index(span.start),
+            line_index(span.end.saturating_sub(1).max(span.start)),
+        )
+    };
+    let mut spans: Vec<MatchSpan> = Vec::new();
+    let location: Location<'_> = Location {
+        path: file_path,
+        line_number: 1,
+        starts: &starts,
+    };
+    let content: String =
+        search_and_replace(&buffer, matcher, &location, counters, &mut spans, |span| {
+            let (first, last): (usize, usize) = line_range(span);
+            accept(&MatchCandidate {
+                path: file_path,
+                line_number: first + 1,
+                end_line_number: last + 1,
+                lines,
+                span: &MatchSpan {
+                    start: span.start - starts[first],
+                    end: span.end - starts[first],
+                    replacement: span.replacement.clone(),
+                    rule: span.rule,
+                    key: span.key,
+                },
+            })
+        })
+        .map_err(|error| replace_error(line_index(error.start) + 1, error))?
+        .into_owned();
+    for span in spans {
+        let (first, last): (usize, usize) = line_range(&span);
+        let line_match: &mut LineMatch = match line_matches.last_mut() {
+            Some(line_match) if first < line_match.end_line_number => line_match,
+            _ => {
+                line_matches.push(LineMatch {
+                    line_number: first + 1,
+                    end_line_number: first + 1,
+                    line: String::new(),
+                    spans: Vec::new(),
+                });
+                line_matches.last_mut().expect("just pushed")
+            }
+        };
+        let offset: usize = starts[line_match.line_number - 1];
+        line_match.end_line_number = line_match.end_line_number.max(last + 1);
+        line_match.spans.push(MatchSpan {
+            start: span.start - offset,
+            end: span.end - offset,
+            replacement: span.replacement,
+            rule: span.rule,
+            key: span.key,
+        });
     }
-    Ok(())
+    for line_match in &mut line_matches {
+        line_match.line = lines[line_match.line_number - 1..line_match.end_line_number].concat();
+    }
+    Ok((content, line_matches))
 }
 
-fn search_and_replace<'a>(
-    line: &'a str,
-    regex: &Regex,
-    replace: &str,
-    found_and_replaced: &mut bool,
-) -> Cow<'a, str> {
-    if regex.is_match(line) {
-        *found_and_replaced = true;
-        return regex.replace_all(line, replace);
+/// Reads a UTF-8 encoded file, every line keeps its line ending
+fn read_lines(file_path: &Path) -> Result<Vec<String>, SarError> {
+    let mut lines: Vec<String> = Vec::new();
+    let file: File = File::open(file_path).map_err(|error| SarError::Read {
+        path: file_path.to_path_buf(),
+        source: error,
+    })?;
+    let mut reader: BufReader<File> = BufReader::new(file);
+    loop {
+        let mut line: String = String::new();
+        let len: usize = reader
+            .read_line(&mut line)
+            .map_err(|error| match error.kind() {
+                ErrorKind::InvalidData => SarError::Decode {
+                    path: file_path.to_path_buf(),
+                    line: lines.len() + 1,
+                },
+                _ => SarError::Read {
+                    path: file_path.to_path_buf(),
+                    source: error,
+                },
+            })?;
+        if len == 0 {
+            return Ok(lines);
+        }
+        lines.push(line);
     }
-    return Cow::from(line);
 }
 
-fn write_file(lines: Vec<String>, path: &Path) -> Result<()> {
-    let file: File = File::create(path)?;
-    let mut writer: LineWriter<File> = LineWriter::new(file);
-    for line in lines {
-        writer.write_all(line.as_bytes())?;
+fn file_diff(file_path: &Path, replaced: &Replaced, options: &ReplaceOptions) -> Option<String> {
+    options.diff_context().map(|context_lines| {
+        unified_diff(
+            &replaced.original,
+            &replaced.content,
+            &diff::patch_path(options.directory(), file_path),
+            context_lines,
+        )
+    })
+}
+
+fn backup_path(file_path: &Path, options: &ReplaceOptions) -> Option<PathBuf> {
+    options
+        .backup()
+        .map(|backup| backup.path_for(options.directory(), file_path))
+}
+
+/// Backs up and journals the original file, then replaces it with the staged content. Returns
+/// where the backup was saved
+fn commit_file(
+    file_path: &Path,
+    content: &str,
+    staged: write::StagedFile,
+    backup_path: Option<&Path>,
+    mut journal: Option<&mut Journal>,
+) -> Result<Option<PathBuf>, SarError> {
+    let backup: Option<PathBuf> = match backup_path {
+        Some(backup_path) => Some(backup::create_backup(file_path, backup_path).map_err(
+            |error| SarError::Backup {
+                path: file_path.to_path_buf(),
+                backup_path: backup_path.to_path_buf(),
+                source: error,
+            },
+        )?),
+        None => None,
+    };
+    if let Some(journal) = journal.as_deref_mut() {
+        journal.record(file_path, content.as_bytes())?;
+    }
+    if let Err(error) = staged.commit() {
+        if let Some(journal) = journal {
+            journal.forget(file_path)?;
+        }
+        return Err(SarError::Write {
+            path: file_path.to_path_buf(),
+            source: error,
+        });
     }
-    writer.flush()?;
-    Ok(())
+    Ok(backup)
 }
 
-pub fn validate_file_extensions(file_extensions: &Vec<String>) -> bool {
-    for file_extension in file_extensions {
-        if file_extension.starts_with(".") || file_extension.contains("*") {
-            return false;
+/// Replaces every match in `line` which is accepted, the accepted matches are added to `spans` and
+/// numbered in `counters`. `location` is where `line` is
+fn search_and_replace<'a, F: FnMut(&MatchSpan) -> bool>(
+    line: &'a str,
+    matcher: &Matcher,
+    location: &Location<'_>,
+    counters: &mut Counters,
+    spans: &mut Vec<MatchSpan>,
+    mut accept: F,
+) -> Result<Cow<'a, str>, ExpandError> {
+    let mut replaced: String = String::new();
+    let mut last: usize = 0;
+    for found in matcher.find(line) {
+        let span: MatchSpan = match matcher.expand(found, counters, location)? {
+            Some(span) => span,
+            None => continue,
+        };
+        if accept(&span) {
+            matcher.count(&span, counters);
+            replaced.push_str(&line[last..span.start]);
+            replaced.push_str(&span.replacement);
+            last = span.end;
+            spans.push(span);
         }
     }
-    return true;
+    if spans.is_empty() {
+        return Ok(Cow::from(line));
+    }
+    replaced.push_str(&line[last..]);
+    Ok(Cow::from(replaced))
+}
+
+pub fn validate_file_extensions(file_extensions: &[String]) -> bool {
+    !file_extensions
+        .iter()
+        .any(|file_extension| file_extension.starts_with('.') || file_extension.contains('*'))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::core::{is_matching_file, search_and_replace, validate_file_extensions};
+    use crate::core::{
+        build_matchers, build_regex, is_matching_file, replace_lines, search_and_replace,
+        validate_file_extensions, walk_directory, Backup, Counters, LineMatch, Location, MatchSpan,
+        Matcher, PatternSpan, ReplaceOptions, SarError,
+    };
     use regex::Regex;
     use std::borrow::Cow;
+    use std::path::{Path, PathBuf};
+
+    fn location() -> Location<'static> {
+        Location {
+            path: Path::new("file.txt"),
+            line_number: 1,
+            starts: &[0],
+        }
+    }
+
+    #[test]
+    fn walk_directory_skips_state_dirs() {
+        let directory: tempfile::TempDir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(directory.path().join("src")).unwrap();
+        std::fs::write(directory.path().join("src").join("a.txt"), "").unwrap();
+        // neither directory exists when the options are built
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .directory(directory.path())
+            .backup(Backup::Directory(directory.path().join("src/../backup")))
+            .journal_dir(directory.path().join("state").join("sar"))
+            .build();
+        for state_dir in ["backup", "state/sar"] {
+            std::fs::create_dir_all(directory.path().join(state_dir)).unwrap();
+            std::fs::write(directory.path().join(state_dir).join("a.txt"), "").unwrap();
+        }
+        let mut files: Vec<PathBuf> = Vec::new();
+        walk_directory(&options, |file_path| files.push(file_path.to_path_buf())).unwrap();
+        assert_eq!(files, vec![directory.path().join("src").join("a.txt")]);
+    }
+
     #[test]
     fn search_and_replace_positive() {
-        let mut found_and_replaced: bool = false;
+        let mut matches: Vec<MatchSpan> = Vec::new();
         let original_text =
             "import { BrowserModule } @igniteui/ from \"@igniteui/platform-browser\";".to_string();
         let expected_text = "import { BrowserModule }  from \"platform-browser\";".to_string();
         let regex: Regex = Regex::new("@igniteui/").unwrap();
-        let result: Cow<str> =
-            search_and_replace(&original_text, &regex, "", &mut found_and_replaced);
+        let result: Cow<str> = search_and_replace(
+            &original_text,
+            &Matcher::from_regex(regex, ""),
+            &location(),
+            &mut Counters::new(&ReplaceOptions::builder().build()),
+            &mut matches,
+            |_| true,
+        )
+        .unwrap();
         assert_eq!(result, expected_text);
-        assert_eq!(found_and_replaced, true);
+        assert_eq!(matches.len(), 2);
     }
     #[test]
     fn search_and_replace_negative() {
-        let mut found_and_replaced: bool = false;
+        let mut matches: Vec<MatchSpan> = Vec::new();
         let original_text = "import { BrowserModule } from \"platform-browser\";".to_string();
         let expected_text = "import { BrowserModule } from \"platform-browser\";".to_string();
         let regex: Regex = Regex::new("@igniteui/").unwrap();
-        let result: Cow<str> =
-            search_and_replace(&original_text, &regex, "", &mut found_and_replaced);
+        let result: Cow<str> = search_and_replace(
+            &original_text,
+            &Matcher::from_regex(regex, ""),
+            &location(),
+            &mut Counters::new(&ReplaceOptions::builder().build()),
+            &mut matches,
+            |_| true,
+        )
+        .unwrap();
         assert_eq!(result, expected_text);
-        assert_eq!(found_and_replaced, false);
+        assert_eq!(matches.len(), 0);
     }
 
     #[test]
     fn search_and_replace_regex_positive_1() {
-        let mut found_and_replaced: bool = false;
+        let mut matches: Vec<MatchSpan> = Vec::new();
         let original_text = r"2012-03-14, 2013-01-01 and 2014-07-05".to_string();
         let expected_text = r"03/14/2012, 01/01/2013 and 07/05/2014".to_string();
         let regex: Regex = Regex::new(r"(?P<y>\d{4})-(?P<m>\d{2})-(?P<d>\d{2})").unwrap();
-        let result: Cow<str> =
-            search_and_replace(&original_text, &regex, "$m/$d/$y", &mut found_and_replaced);
+        let result: Cow<str> = search_and_replace(
+            &original_text,
+            &Matcher::from_regex(regex, "$m/$d/$y"),
+            &location(),
+            &mut Counters::new(&ReplaceOptions::builder().build()),
+            &mut matches,
+            |_| true,
+        )
+        .unwrap();
         assert_eq!(result, expected_text);
-        assert_eq!(found_and_replaced, true);
+        assert_eq!(matches.len(), 3);
     }
 
     #[test]
     fn search_and_replace_regex_positive_2() {
-        let mut found_and_replaced: bool = false;
+        let mut matches: Vec<MatchSpan> = Vec::new();
         let original_text = r"www.xyz.123".to_string();
         let expected_text = r"www.xyz.com".to_string();
         let regex: Regex = Regex::new(r"(?P<a>[a-z]{3})\.(?P<b>[a-z]{3})\.(?P<c>\d{3})").unwrap();
         let result: Cow<str> = search_and_replace(
             &original_text,
-            &regex,
-            r"$a.$b.com",
-            &mut found_and_replaced,
-        );
+            &Matcher::from_regex(regex, r"$a.$b.com"),
+            &location(),
+            &mut Counters::new(&ReplaceOptions::builder().build()),
+            &mut matches,
+            |_| true,
+        )
+        .unwrap();
         assert_eq!(result, expected_text);
-        assert_eq!(found_and_replaced, true);
+        assert_eq!(matches.len(), 1);
     }
 
     #[test]
     fn search_and_replace_regex_negative() {
-        let mut found_and_replaced: bool = false;
+        let mut matches: Vec<MatchSpan> = Vec::new();
         let original_text = r"03/14/2012, 01/01/2013 and 07/05/2014".to_string();
         let expected_text = r"03/14/2012, 01/01/2013 and 07/05/2014".to_string();
         let regex: Regex = Regex::new(r"(?P<y>\d{4})-(?P<m>\d{2})-(?P<d>\d{2})").unwrap();
-        let result: Cow<str> =
-            search_and_replace(&original_text, &regex, "$m/$d/$y", &mut found_and_replaced);
+        let result: Cow<str> = search_and_replace(
+            &original_text,
+            &Matcher::from_regex(regex, "$m/$d/$y"),
+            &location(),
+            &mut Counters::new(&ReplaceOptions::builder().build()),
+            &mut matches,
+            |_| true,
+        )
+        .unwrap();
         assert_eq!(result, expected_text);
-        assert_eq!(found_and_replaced, false);
+        assert_eq!(matches.len(), 0);
     }
 
     #[test]
@@ -212,38 +762,167 @@ mod tests {
         let file_extensions: Vec<String> = vec!["txt".to_string(), "json".to_string()];
         let entry: Option<&str> = Some("test.json");
         let is_matching_file = is_matching_file(entry, &file_extensions);
-        assert_eq!(is_matching_file, true);
+        assert!(is_matching_file);
     }
     #[test]
     fn is_matching_file_negative() {
         let file_extensions: Vec<String> = vec!["txt".to_string(), "json".to_string()];
         let entry: Option<&str> = Some("test.js");
         let is_matching_file = is_matching_file(entry, &file_extensions);
-        assert_eq!(is_matching_file, false);
+        assert!(!is_matching_file);
     }
     #[test]
     fn is_matching_file_no_file_extensions() {
         let file_extensions: Vec<String> = Vec::new();
         let entry: Option<&str> = Some("test.js");
         let is_matching_file = is_matching_file(entry, &file_extensions);
-        assert_eq!(is_matching_file, true);
+        assert!(is_matching_file);
     }
     #[test]
     fn validate_file_extensions_positive() {
         let file_extensions: Vec<String> = vec!["txt".to_string(), "json".to_string()];
         let validate_file_extensions = validate_file_extensions(&file_extensions);
-        assert_eq!(validate_file_extensions, true);
+        assert!(validate_file_extensions);
     }
     #[test]
     fn validate_file_extensions_negative_1() {
         let file_extensions: Vec<String> = vec!["*.txt".to_string(), "json".to_string()];
         let validate_file_extensions = validate_file_extensions(&file_extensions);
-        assert_eq!(validate_file_extensions, false);
+        assert!(!validate_file_extensions);
     }
     #[test]
     fn validate_file_extensions_negative_2() {
         let file_extensions: Vec<String> = vec!["txt".to_string(), ".json".to_string()];
         let validate_file_extensions = validate_file_extensions(&file_extensions);
-        assert_eq!(validate_file_extensions, false);
+        assert!(!validate_file_extensions);
+    }
+    #[test]
+    fn build_regex_invalid_pattern() {
+        let options: ReplaceOptions = ReplaceOptions::builder().search("ab[z-a]c").build();
+        match build_regex(&options.rules()[0], &options) {
+            Err(SarError::InvalidPattern {
+                pattern,
+                message,
+                span,
+                ..
+            }) => {
+                assert_eq!(pattern, "ab[z-a]c");
+                assert_eq!(
+                    message,
+                    "invalid character class range, the start must be <= the end"
+                );
+                assert_eq!(
+                    span,
+                    Some(PatternSpan {
+                        start: 3,
+                        end: 6,
+                        line: 1,
+                        column: 4
+                    })
+                );
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn search_and_replace_accepted_matches_only() {
+        let mut matches: Vec<MatchSpan> = Vec::new();
+        let regex: Regex = Regex::new(r"(?P<a>[a-z]{3})\.(?P<b>\d{3})").unwrap();
+        let result: Cow<str> = search_and_replace(
+            "abc.123 and xyz.456\n",
+            &Matcher::from_regex(regex, "$b.$a"),
+            &location(),
+            &mut Counters::new(&ReplaceOptions::builder().build()),
+            &mut matches,
+            |span| span.start > 0,
+        )
+        .unwrap();
+        assert_eq!(result, "abc.123 and 456.xyz\n");
+        assert_eq!(
+            matches,
+            vec![MatchSpan {
+                start: 12,
+                end: 19,
+                replacement: String::from("456.xyz"),
+                rule: 0,
+                key: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn search_and_replace_numbers_accepted_matches_only() {
+        let mut matches: Vec<MatchSpan> = Vec::new();
+        let mut counters: Counters = Counters::new(&ReplaceOptions::builder().build());
+        let matcher: Matcher = Matcher::from_regex(Regex::new("id").unwrap(), "${counter:start=0}");
+        let result: Cow<str> = search_and_replace(
+            "id id id id\n",
+            &matcher,
+            &location(),
+            &mut counters,
+            &mut matches,
+            |span| span.start != 3,
+        )
+        .unwrap();
+        assert_eq!(result, "0 id 1 2\n");
+        counters.start_file();
+        let result: Cow<str> = search_and_replace(
+            "id\n",
+            &matcher,
+            &location(),
+            &mut counters,
+            &mut Vec::new(),
+            |_| true,
+        )
+        .unwrap();
+        assert_eq!(result, "3\n");
+    }
+
+    #[test]
+    fn replace_lines_multiline_groups_lines() {
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .search(r"b,\n\s*c|d$")
+            .replace("X")
+            .multiline(true)
+            .build();
+        let lines: Vec<String> = ["a\n", "b,\n", "  c d\n", "e\n"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        let (content, line_matches): (String, Vec<LineMatch>) = replace_lines(
+            Path::new("file.txt"),
+            &lines,
+            &build_matchers(&options).unwrap()[0],
+            &options,
+            &mut Counters::new(&options),
+            |_| true,
+        )
+        .unwrap();
+        assert_eq!(content, "a\nX X\ne\n");
+        assert_eq!(
+            line_matches,
+            vec![LineMatch {
+                line_number: 2,
+                end_line_number: 3,
+                line: String::from("b,\n  c d\n"),
+                spans: vec![
+                    MatchSpan {
+                        start: 0,
+                        end: 6,
+                        replacement: String::from("X"),
+                        rule: 0,
+                        key: None,
+                    },
+                    MatchSpan {
+                        start: 7,
+                        end: 8,
+                        replacement: String::from("X"),
+                        rule: 0,
+                        key: None,
+                    },
+                ],
+            }]
+        );
     }
 }
@@ -0,0 +1,498 @@
+use super::Backup;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A search text, its replace text and how the search text is matched, see
+/// [`ReplaceOptionsBuilder::rule`]. The options are the same as the ones of the builder
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rule {
+    pub search: String,
+    pub replace: String,
+    /// See [`ReplaceOptionsBuilder::fixed_strings`]
+    pub fixed_strings: bool,
+    /// See [`ReplaceOptionsBuilder::literal_replace`]
+    pub literal_replace: bool,
+    /// See [`ReplaceOptionsBuilder::case_spans`]
+    pub case_spans: bool,
+    /// See [`ReplaceOptionsBuilder::ignore_case`]
+    pub ignore_case: bool,
+    /// See [`ReplaceOptionsBuilder::smart_case`]
+    pub smart_case: bool,
+    /// See [`ReplaceOptionsBuilder::word_regexp`]
+    pub word_regexp: bool,
+    /// See [`ReplaceOptionsBuilder::preserve_case`]
+    pub preserve_case: bool,
+    /// Globs of the files the rule applies to, on top of the ones of the run. All files if empty
+    pub include: Vec<String>,
+    /// Globs of the files the rule does not apply to
+    pub exclude: Vec<String>,
+    /// Sub directories whose files the rule does not apply to
+    pub ignored_dirs: Vec<String>,
+    /// Keys and the values they are replaced with, matched instead of the search text. The
+    /// longest key found at the leftmost position wins, values are inserted as is. See
+    /// [`load_mapping`](super::load_mapping)
+    pub map: Vec<(String, String)>,
+    /// Mapping file the keys of [`Rule::map`] were loaded from, recorded in the undo journal
+    pub map_file: Option<PathBuf>,
+    /// Old and new name of an identifier, matched instead of the search text, see
+    /// [`ReplaceOptionsBuilder::rename_identifier`]
+    pub identifier: Option<(String, String)>,
+    /// Replace script run for every match instead of expanding the replace text, see
+    /// [`ReplaceOptionsBuilder::replace_script`]
+    pub script: Option<PathBuf>,
+}
+
+impl Rule {
+    pub fn new<S: Into<String>, R: Into<String>>(search: S, replace: R) -> Rule {
+        Rule {
+            search: search.into(),
+            replace: replace.into(),
+            ..Rule::default()
+        }
+    }
+}
+
+/// Whether a rule sees the replacements of the rules before it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RuleOrder {
+    /// Rules are applied one after the other, each to the output of the rules before it, like
+    /// `sed -e a -e b`
+    #[default]
+    Sequential,
+    /// Every rule matches the original text. Where matches of several rules overlap, the match
+    /// starting first wins, then the rule given first. Replaced text is never matched again
+    Simultaneous,
+}
+
+/// What `${counter}` in a replace text counts, see [`ReplaceOptionsBuilder::counter_scope`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CounterScope {
+    /// A single counter across every file and rule of the run
+    #[default]
+    Global,
+    /// The counter starts over in every file
+    File,
+    /// Every rule has its own counter across the run
+    Rule,
+}
+
+/// Everything a single search and replace run needs. Use [`ReplaceOptions::builder`] to create one.
+#[derive(Debug, Clone)]
+pub struct ReplaceOptions {
+    directory: PathBuf,
+    file_extensions: Vec<String>,
+    ignored_dirs: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    rules: Vec<Rule>,
+    rule_order: RuleOrder,
+    counter_scope: CounterScope,
+    dry_run: bool,
+    preserve_mtime: bool,
+    backup: Option<Backup>,
+    journal_dir: Option<PathBuf>,
+    state_dirs: Vec<PathBuf>,
+    atomic: bool,
+    diff_context: Option<usize>,
+    preview: bool,
+    multiline: bool,
+}
+
+impl ReplaceOptions {
+    pub fn builder() -> ReplaceOptionsBuilder {
+        ReplaceOptionsBuilder::default()
+    }
+
+    pub fn directory(&self) -> &Path {
+        self.directory.as_path()
+    }
+
+    pub fn file_extensions(&self) -> &[String] {
+        &self.file_extensions
+    }
+
+    pub fn ignored_dirs(&self) -> &[String] {
+        &self.ignored_dirs
+    }
+
+    pub fn include(&self) -> &[String] {
+        &self.include
+    }
+
+    pub fn exclude(&self) -> &[String] {
+        &self.exclude
+    }
+
+    /// Search text of the first rule
+    pub fn search(&self) -> &str {
+        self.rules[0].search.as_str()
+    }
+
+    /// Replace text of the first rule
+    pub fn replace(&self) -> &str {
+        self.rules[0].replace.as_str()
+    }
+
+    /// Every rule of the run in the order given, never empty
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn rule_order(&self) -> RuleOrder {
+        self.rule_order
+    }
+
+    pub fn counter_scope(&self) -> CounterScope {
+        self.counter_scope
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn preserve_mtime(&self) -> bool {
+        self.preserve_mtime
+    }
+
+    pub fn backup(&self) -> Option<&Backup> {
+        self.backup.as_ref()
+    }
+
+    pub fn journal_dir(&self) -> Option<&Path> {
+        self.journal_dir.as_deref()
+    }
+
+    /// Canonical paths of the backup directory and the journal directory, which are never
+    /// searched. Taken when the options are built, a directory which does not exist yet is
+    /// resolved from its nearest existing parent
+    pub(crate) fn state_dirs(&self) -> &[PathBuf] {
+        &self.state_dirs
+    }
+
+    pub fn atomic(&self) -> bool {
+        self.atomic
+    }
+
+    /// Number of context lines of the unified diff reported for every file, `None` if no diff
+    pub fn diff_context(&self) -> Option<usize> {
+        self.diff_context
+    }
+
+    pub fn preview(&self) -> bool {
+        self.preview
+    }
+
+    pub fn fixed_strings(&self) -> bool {
+        self.rules[0].fixed_strings
+    }
+
+    pub fn literal_replace(&self) -> bool {
+        self.rules[0].literal_replace
+    }
+
+    pub fn case_spans(&self) -> bool {
+        self.rules[0].case_spans
+    }
+
+    pub fn ignore_case(&self) -> bool {
+        self.rules[0].ignore_case
+    }
+
+    pub fn smart_case(&self) -> bool {
+        self.rules[0].smart_case
+    }
+
+    pub fn word_regexp(&self) -> bool {
+        self.rules[0].word_regexp
+    }
+
+    pub fn preserve_case(&self) -> bool {
+        self.rules[0].preserve_case
+    }
+
+    pub fn multiline(&self) -> bool {
+        self.multiline
+    }
+}
+
+/// Builder for [`ReplaceOptions`]. Directory defaults to the current directory ("."), replace
+/// defaults to a blank ("") value, no file extensions means all files are included.
+#[derive(Debug, Clone, Default)]
+pub struct ReplaceOptionsBuilder {
+    directory: Option<PathBuf>,
+    file_extensions: Vec<String>,
+    ignored_dirs: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    /// First rule, set with the search and replace text and the match options
+    rule: Rule,
+    rules: Vec<Rule>,
+    rule_order: RuleOrder,
+    counter_scope: CounterScope,
+    dry_run: bool,
+    preserve_mtime: bool,
+    backup: Option<Backup>,
+    journal_dir: Option<PathBuf>,
+    atomic: bool,
+    diff_context: Option<usize>,
+    preview: bool,
+    multiline: bool,
+}
+
+impl ReplaceOptionsBuilder {
+    pub fn directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    pub fn file_extensions<I, S>(mut self, file_extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.file_extensions = file_extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn ignored_dirs<I, S>(mut self, ignored_dirs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.ignored_dirs = ignored_dirs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only search the files matching one of the globs, e.g. `src/**/*.rs`. Globs are matched
+    /// against the path relative to the directory, `*` also matches `/`
+    pub fn include<I, S>(mut self, include: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.include = include.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Never search the files matching one of the globs, see [`include`](Self::include)
+    pub fn exclude<I, S>(mut self, exclude: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude = exclude.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn search<S: Into<String>>(mut self, search: S) -> Self {
+        self.rule.search = search.into();
+        self
+    }
+
+    pub fn replace<S: Into<String>>(mut self, replace: S) -> Self {
+        self.rule.replace = replace.into();
+        self
+    }
+
+    /// Add a rule after the search and replace text, every rule is applied to each file in a
+    /// single walk. The match options of the builder only apply to the first rule, a rule has its
+    /// own. Without a search text the added rules are the only ones
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Whether a rule sees the replacements of the rules before it, see [`RuleOrder`]
+    pub fn rule_order(mut self, rule_order: RuleOrder) -> Self {
+        self.rule_order = rule_order;
+        self
+    }
+
+    /// Whether `${counter}` counts the matches of the whole run, of every file or of every rule.
+    /// Files are walked sorted by name, so the numbers are the same in every run
+    pub fn counter_scope(mut self, counter_scope: CounterScope) -> Self {
+        self.counter_scope = counter_scope;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Keep the access and modification times of edited files, so build systems don't rebuild them
+    pub fn preserve_mtime(mut self, preserve_mtime: bool) -> Self {
+        self.preserve_mtime = preserve_mtime;
+        self
+    }
+
+    /// Keep the pre-edit version of every edited file. No backups are made by default
+    pub fn backup(mut self, backup: Backup) -> Self {
+        self.backup = Some(backup);
+        self
+    }
+
+    /// Write an undo journal for non dry runs into the state directory, see [`undo`](super::undo).
+    /// No journal is written by default
+    pub fn journal_dir<P: Into<PathBuf>>(mut self, journal_dir: P) -> Self {
+        self.journal_dir = Some(journal_dir.into());
+        self
+    }
+
+    /// All or nothing: compute every replacement and stage every write first, then edit the files
+    /// only if all of them succeeded. Files already edited are restored if a late step fails
+    pub fn atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+
+    /// Report a unified diff with `context_lines` lines of context for every file where the search
+    /// text was found, see [`FileReport::diff`](super::FileReport::diff). No diff by default
+    pub fn diff(mut self, context_lines: usize) -> Self {
+        self.diff_context = Some(context_lines);
+        self
+    }
+
+    /// Report every line where the search text was found, with the span and replacement of every
+    /// match, see [`FileReport::lines`](super::FileReport::lines). No lines are reported by default
+    pub fn preview(mut self, preview: bool) -> Self {
+        self.preview = preview;
+        self
+    }
+
+    /// Match the search text literally instead of as a regex, e.g. `a.b(c)` only matches `a.b(c)`
+    pub fn fixed_strings(mut self, fixed_strings: bool) -> Self {
+        self.rule.fixed_strings = fixed_strings;
+        self
+    }
+
+    /// Insert the replace text as is, `$name` and `${name}` are not expanded to captured groups
+    pub fn literal_replace(mut self, literal_replace: bool) -> Self {
+        self.rule.literal_replace = literal_replace;
+        self
+    }
+
+    /// Let `\U` and `\L` in the replace text turn everything up to `\E` (or the end of the
+    /// replacement) upper or lower case, like in sed and perl, e.g. `\U$1\E_ID`. Off by default,
+    /// backslashes are then inserted as is, e.g. in Windows paths like `C:\Users\Elena`
+    pub fn case_spans(mut self, case_spans: bool) -> Self {
+        self.rule.case_spans = case_spans;
+        self
+    }
+
+    /// Match the search text case insensitively, Unicode case folding applies
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.rule.ignore_case = ignore_case;
+        self
+    }
+
+    /// Match case insensitively if the search text has no uppercase letter. Escapes like `\S` are
+    /// not letters. Has no effect with [`ignore_case`](Self::ignore_case)
+    pub fn smart_case(mut self, smart_case: bool) -> Self {
+        self.rule.smart_case = smart_case;
+        self
+    }
+
+    /// Match whole words only, as if the search text was surrounded by Unicode aware `\b`
+    pub fn word_regexp(mut self, word_regexp: bool) -> Self {
+        self.rule.word_regexp = word_regexp;
+        self
+    }
+
+    /// Rename an identifier in every case style instead of searching the search text, e.g.
+    /// `rename_identifier("order item", "line item")` replaces `orderItem`, `OrderItem`,
+    /// `order_item`, `order-item` and `ORDER_ITEM` with the same style of `line item`. Names are
+    /// words separated by spaces, `_` or `-`, or written in camel case. A match has to start and
+    /// end at a word of an identifier: `getOrderItem` and `order_item_id` are renamed,
+    /// `reorder_item` and `orderItems` are not. Sets the search and replace text to the names
+    pub fn rename_identifier<O: Into<String>, N: Into<String>>(mut self, old: O, new: N) -> Self {
+        let (old, new): (String, String) = (old.into(), new.into());
+        self.rule.search = old.clone();
+        self.rule.replace = new.clone();
+        self.rule.identifier = Some((old, new));
+        self
+    }
+
+    /// Match the search text case insensitively and give every replacement the case of its match:
+    /// lower, upper or title case, e.g. `widget` replaced with `gadget` turns `Widget` into
+    /// `Gadget` and `WIDGET` into `GADGET`. A mixed case like `WidgetBox` is copied letter by
+    /// letter if the replacement has as many characters, otherwise the replacement is left as is
+    pub fn preserve_case(mut self, preserve_case: bool) -> Self {
+        self.rule.preserve_case = preserve_case;
+        self
+    }
+
+    /// Replace every match with the value of a Rhai script instead of the replace text. The script
+    /// is given `captures` (the whole match, then every group), `groups` (the named groups), `path`
+    /// and `line`, and returns the replacement or `()` to leave the match alone. It has no file or
+    /// network access, and fails a match after a million operations
+    pub fn replace_script<P: Into<PathBuf>>(mut self, script: P) -> Self {
+        self.rule.script = Some(script.into());
+        self
+    }
+
+    /// Match the search text against the whole file instead of line by line, so a match can span
+    /// several lines. `^` and `$` match at line boundaries, `(?s)` lets `.` match line endings
+    pub fn multiline(mut self, multiline: bool) -> Self {
+        self.multiline = multiline;
+        self
+    }
+
+    pub fn build(self) -> ReplaceOptions {
+        let mut rules: Vec<Rule> = Vec::new();
+        if !self.rule.search.is_empty() || self.rules.is_empty() {
+            rules.push(self.rule);
+        }
+        rules.extend(self.rules);
+        let state_dirs: Vec<PathBuf> = match &self.backup {
+            Some(Backup::Directory(backup_directory)) => Some(backup_directory.as_path()),
+            _ => None,
+        }
+        .into_iter()
+        .chain(self.journal_dir.as_deref())
+        .filter_map(canonical_path)
+        .collect();
+        ReplaceOptions {
+            directory: self.directory.unwrap_or_else(|| PathBuf::from(".")),
+            file_extensions: self.file_extensions,
+            ignored_dirs: self.ignored_dirs,
+            include: self.include,
+            exclude: self.exclude,
+            rules,
+            rule_order: self.rule_order,
+            counter_scope: self.counter_scope,
+            dry_run: self.dry_run,
+            preserve_mtime: self.preserve_mtime,
+            backup: self.backup,
+            journal_dir: self.journal_dir,
+            state_dirs,
+            atomic: self.atomic,
+            diff_context: self.diff_context,
+            preview: self.preview,
+            multiline: self.multiline,
+        }
+    }
+}
+
+/// Canonical form of `path`, which may not exist yet: the canonical path of its nearest existing
+/// ancestor joined with the rest. `None` if no ancestor can be canonicalized
+fn canonical_path(path: &Path) -> Option<PathBuf> {
+    let mut missing: Vec<&OsStr> = Vec::new();
+    let mut existing: &Path = path;
+    loop {
+        if let Ok(canonical) = fs::canonicalize(existing) {
+            return Some(
+                missing
+                    .into_iter()
+                    .rev()
+                    .fold(canonical, |path, name| path.join(name)),
+            );
+        }
+        missing.push(existing.file_name()?);
+        existing = match existing.parent()? {
+            parent if parent.as_os_str().is_empty() => Path::new("."),
+            parent => parent,
+        };
+    }
+}
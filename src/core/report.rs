@@ -0,0 +1,175 @@
+use super::SarError;
+use std::path::{Path, PathBuf};
+
+/// Result of a single [`process_directory`](super::process_directory) run.
+/// Only files where the search text was found (or which could not be processed) are listed.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub files: Vec<FileReport>,
+    pub dir_errors: Vec<SarError>,
+    /// Id of the undo journal of this run, if a journal was written and any file was edited
+    pub run_id: Option<String>,
+    /// True if an atomic run was aborted, no file was left edited unless its rollback failed
+    pub aborted: bool,
+    /// Keys of a [`Rule::map`](super::Rule::map) which were not found in any file, with the
+    /// index of their rule
+    pub unmatched_keys: Vec<(usize, String)>,
+}
+
+#[derive(Debug)]
+pub struct FileReport {
+    pub path: PathBuf,
+    /// Number of matches of the search text in the file
+    pub matches: usize,
+    /// Number of matches of every rule, indexed like
+    /// [`ReplaceOptions::rules`](super::ReplaceOptions::rules)
+    pub rule_matches: Vec<usize>,
+    /// Number of matches of every key of a rule with a [`map`](super::Rule::map), indexed like
+    /// the rules and their keys. Empty for a rule without a map
+    pub key_matches: Vec<Vec<usize>>,
+    pub outcome: FileOutcome,
+    /// Where the pre-edit version of the file was saved, if backups were requested
+    pub backup: Option<PathBuf>,
+    /// Unified diff of the replacement, if a diff was requested
+    pub diff: Option<String>,
+    /// Every line where the search text was found, if a preview was requested
+    pub lines: Vec<LineMatch>,
+}
+
+/// A line where the search text was found. With
+/// [`ReplaceOptions::multiline`](super::ReplaceOptions::multiline) a match can span several lines,
+/// the lines of overlapping matches are grouped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMatch {
+    /// Line number, starting at 1
+    pub line_number: usize,
+    /// Number of the last line, the same as `line_number` unless a match spans several lines
+    pub end_line_number: usize,
+    /// Original line(s), including line endings
+    pub line: String,
+    pub spans: Vec<MatchSpan>,
+}
+
+/// A single match of the search text in a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchSpan {
+    /// Byte offset of the match from the start of the line
+    pub start: usize,
+    /// Byte offset right after the match from the start of the line
+    pub end: usize,
+    /// Text the match is replaced with
+    pub replacement: String,
+    /// Index of the rule which made the change, see
+    /// [`ReplaceOptions::rules`](super::ReplaceOptions::rules)
+    pub rule: usize,
+    /// Index of the key which matched, if the rule has a [`map`](super::Rule::map)
+    pub key: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum FileOutcome {
+    /// Search text found, file not modified because of dry run
+    Found,
+    /// Search text found and replaced
+    Edited,
+    /// File could not be searched or edited
+    Failed(SarError),
+    /// File was edited, then restored because an atomic run was aborted
+    RolledBack,
+}
+
+impl FileReport {
+    pub(crate) fn failed(path: &Path, matches: usize, error: SarError) -> FileReport {
+        FileReport {
+            path: path.to_path_buf(),
+            matches,
+            rule_matches: Vec::new(),
+            key_matches: Vec::new(),
+            outcome: FileOutcome::Failed(error),
+            backup: None,
+            diff: None,
+            lines: Vec::new(),
+        }
+    }
+}
+
+impl Report {
+    /// Total # of files where search text was found
+    pub fn files_found(&self) -> usize {
+        self.files.iter().filter(|file| file.matches > 0).count()
+    }
+
+    /// Total # of files edited and then restored by an aborted atomic run
+    pub fn files_rolled_back(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.outcome, FileOutcome::RolledBack))
+            .count()
+    }
+
+    /// Total # of files where search text was replaced
+    pub fn files_edited(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.outcome, FileOutcome::Edited))
+            .count()
+    }
+
+    /// Total # of files not searched or edited (error)
+    pub fn files_failed(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.outcome, FileOutcome::Failed(_)))
+            .count()
+    }
+
+    /// Diffs of all files which were not failed, concatenated into a single patch which can be
+    /// applied with `git apply` or `patch -p1` from the searched directory
+    pub fn patch(&self) -> String {
+        self.files
+            .iter()
+            .filter(|file| !matches!(file.outcome, FileOutcome::Failed(_)))
+            .filter_map(|file| file.diff.as_deref())
+            .collect()
+    }
+
+    /// Total # of matches across all files
+    pub fn total_matches(&self) -> usize {
+        self.files.iter().map(|file| file.matches).sum()
+    }
+
+    /// Total # of matches of every rule across all files, indexed like
+    /// [`ReplaceOptions::rules`](super::ReplaceOptions::rules)
+    pub fn rule_matches(&self) -> Vec<usize> {
+        let mut totals: Vec<usize> = Vec::new();
+        for file in &self.files {
+            if totals.len() < file.rule_matches.len() {
+                totals.resize(file.rule_matches.len(), 0);
+            }
+            for (total, matches) in totals.iter_mut().zip(&file.rule_matches) {
+                *total += matches;
+            }
+        }
+        totals
+    }
+
+    /// Total # of matches of every key of a rule with a [`map`](super::Rule::map) across all
+    /// files, indexed like [`FileReport::key_matches`]
+    pub fn key_matches(&self) -> Vec<Vec<usize>> {
+        let mut totals: Vec<Vec<usize>> = Vec::new();
+        for file in &self.files {
+            if totals.len() < file.key_matches.len() {
+                totals.resize(file.key_matches.len(), Vec::new());
+            }
+            for (total, matches) in totals.iter_mut().zip(&file.key_matches) {
+                if total.len() < matches.len() {
+                    total.resize(matches.len(), 0);
+                }
+                for (total, matches) in total.iter_mut().zip(matches) {
+                    *total += matches;
+                }
+            }
+        }
+        totals
+    }
+}
@@ -0,0 +1,222 @@
+use super::{
+    build_matchers, process_directory_interactive, read_lines, replace_passes, walk_directory,
+    Counters, Decision, LineMatch, MatchCandidate, MatchSpan, Matcher, ReplaceOptions, Report,
+    SarError,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Matches found by [`review`], nothing is written until the selected hunks are passed to
+/// [`apply`]. Every hunk is selected initially
+#[derive(Debug, Default)]
+pub struct Review {
+    pub files: Vec<ReviewFile>,
+    /// Files and directories which could not be searched
+    pub errors: Vec<SarError>,
+}
+
+#[derive(Debug)]
+pub struct ReviewFile {
+    pub path: PathBuf,
+    /// Every line of the file as read, including line endings
+    pub lines: Vec<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+/// A line where the search text was found (or several lines, see [`LineMatch`]), all matches in
+/// the line are replaced or none of them. With [`RuleOrder::Sequential`](super::RuleOrder) every
+/// rule has its own hunks, over the lines as the rules before it left them. The hunks of a file
+/// are sorted by line number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// Line number, starting at 1
+    pub line_number: usize,
+    /// Number of the last line, the same as `line_number` unless a match spans several lines
+    pub end_line_number: usize,
+    /// Line(s) as the rules of the hunk saw them, including line endings
+    pub line: String,
+    pub spans: Vec<MatchSpan>,
+    pub selected: bool,
+}
+
+impl Hunk {
+    /// Line(s) of the hunk with every match replaced
+    pub fn replaced(&self) -> String {
+        let mut replaced: String = String::new();
+        let mut last: usize = 0;
+        for span in &self.spans {
+            replaced.push_str(&self.line[last..span.start]);
+            replaced.push_str(&span.replacement);
+            last = span.end;
+        }
+        replaced.push_str(&self.line[last..]);
+        replaced
+    }
+}
+
+impl ReviewFile {
+    /// Total # of selected hunks
+    pub fn selected(&self) -> usize {
+        self.hunks.iter().filter(|hunk| hunk.selected).count()
+    }
+
+    fn is_selected(&self, candidate: &MatchCandidate<'_>) -> bool {
+        self.hunks
+            .iter()
+            .find(|hunk| {
+                hunk.line_number <= candidate.line_number
+                    && candidate.end_line_number <= hunk.end_line_number
+                    && hunk
+                        .spans
+                        .iter()
+                        .any(|span| span.rule == candidate.span.rule)
+            })
+            // lines changed since the review are never replaced
+            .map(|hunk| {
+                let lines = hunk.line_number - 1..hunk.end_line_number;
+                hunk.selected
+                    && candidate
+                        .lines
+                        .get(lines)
+                        .map(|lines| lines.concat())
+                        .as_deref()
+                        == Some(hunk.line.as_str())
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl Review {
+    pub fn toggle(&mut self, file: usize, hunk: usize) {
+        let hunk: &mut Hunk = &mut self.files[file].hunks[hunk];
+        hunk.selected = !hunk.selected;
+    }
+
+    pub fn select_file(&mut self, file: usize, selected: bool) {
+        for hunk in &mut self.files[file].hunks {
+            hunk.selected = selected;
+        }
+    }
+
+    /// Total # of selected hunks across all files
+    pub fn selected(&self) -> usize {
+        self.files.iter().map(ReviewFile::selected).sum()
+    }
+}
+
+/// Searches the files like a dry run of [`process_directory`](super::process_directory) and
+/// returns every matching line as a selectable hunk
+pub fn review(options: &ReplaceOptions) -> Result<Review, SarError> {
+    let matchers: Vec<Matcher> = build_matchers(options)?;
+    let mut counters: Counters = Counters::new(options);
+    let mut review: Review = Review::default();
+    let mut errors: Vec<SarError> = Vec::new();
+    let dir_errors: Vec<SarError> = walk_directory(options, |file_path| {
+        let found: Result<(Vec<String>, Vec<LineMatch>), SarError> = read_lines(file_path)
+            .and_then(|lines| {
+                counters.start_file();
+                replace_passes(
+                    file_path,
+                    lines.clone(),
+                    &matchers,
+                    options,
+                    &mut counters,
+                    |_| true,
+                )
+                .map(|(_, line_matches)| (lines, line_matches))
+            });
+        match found {
+            Ok((lines, line_matches)) => {
+                let mut hunks: Vec<Hunk> = line_matches
+                    .into_iter()
+                    .map(|line_match| Hunk {
+                        line_number: line_match.line_number,
+                        end_line_number: line_match.end_line_number,
+                        line: line_match.line,
+                        spans: line_match.spans,
+                        selected: true,
+                    })
+                    .collect();
+                // the hunks of every rule in the order of their lines, a line can have several
+                hunks.sort_by_key(|hunk| hunk.line_number);
+                if !hunks.is_empty() {
+                    review.files.push(ReviewFile {
+                        path: file_path.to_path_buf(),
+                        lines,
+                        hunks,
+                    });
+                }
+            }
+            Err(error) => errors.push(error),
+        }
+    })?;
+    errors.extend(dir_errors);
+    review.errors = errors;
+    Ok(review)
+}
+
+/// Replaces the selected hunks of `review`, which must have been created with the same `options`.
+/// Lines changed since the review are left untouched
+pub fn apply(options: &ReplaceOptions, review: &Review) -> Result<Report, SarError> {
+    let files: HashMap<&Path, &ReviewFile> = review
+        .files
+        .iter()
+        .map(|file| (file.path.as_path(), file))
+        .collect();
+    process_directory_interactive(options, |candidate| {
+        let selected: bool = files
+            .get(candidate.path)
+            .map(|file| file.is_selected(candidate))
+            .unwrap_or(false);
+        if selected {
+            Decision::Yes
+        } else {
+            Decision::No
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::review::{Hunk, ReviewFile};
+    use crate::core::{MatchCandidate, MatchSpan};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn review_file_replaced_and_selected_lines() {
+        let hunk: Hunk = Hunk {
+            line_number: 2,
+            end_line_number: 2,
+            line: String::from("www.123\n"),
+            spans: vec![MatchSpan {
+                start: 4,
+                end: 7,
+                replacement: String::from("com"),
+                rule: 0,
+                key: None,
+            }],
+            selected: true,
+        };
+        let file: ReviewFile = ReviewFile {
+            path: PathBuf::from("file.txt"),
+            lines: vec![String::from("www.abc.com\n"), String::from("www.123\n")],
+            hunks: vec![hunk.clone()],
+        };
+        assert_eq!(hunk.replaced(), "www.com\n");
+        let candidate = |line_number: usize, lines: &[String]| {
+            file.is_selected(&MatchCandidate {
+                path: Path::new("file.txt"),
+                line_number,
+                end_line_number: line_number,
+                lines,
+                span: &hunk.spans[0],
+            })
+        };
+        assert!(candidate(2, &file.lines));
+        assert!(!candidate(1, &file.lines));
+        assert!(!candidate(
+            2,
+            &[String::from("www.abc.com\n"), String::from("www.456\n")]
+        ));
+    }
+}
@@ -0,0 +1,463 @@
+use super::filter::FileFilter;
+use super::{
+    build_matchers, replace_lines, validate_file_extensions, CounterScope, Counters, Matcher,
+    ReplaceOptions, ReplaceOptionsBuilder, Rule, RuleOrder, SarError,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Version of the rules file format written by this version of sar
+pub const RULES_FILE_VERSION: u64 = 1;
+
+/// A rules file read by [`load_rules`]
+#[derive(Debug, Clone)]
+pub struct RulesFile {
+    pub path: PathBuf,
+    /// Every rule with the defaults of the file applied, add the run options (e.g.
+    /// [`dry_run`](ReplaceOptionsBuilder::dry_run)) before building
+    pub options: ReplaceOptionsBuilder,
+    /// Name of every rule, if it has one
+    pub names: Vec<Option<String>>,
+    /// Examples of every rule, indexed like the rules, see [`run_examples`]
+    pub examples: Vec<Vec<RuleExample>>,
+}
+
+/// Text a rule is expected to turn `input` into
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleExample {
+    pub input: String,
+    pub output: String,
+}
+
+/// Outcome of a single [`RuleExample`], see [`run_examples`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExampleResult {
+    /// Index of the rule, indexed like [`RulesFile::names`]
+    pub rule: usize,
+    /// Index of the example within its rule
+    pub example: usize,
+    pub input: String,
+    pub expected: String,
+    /// Text the rule turned the input into, the input if a replacement failed
+    pub actual: String,
+    /// Why a replacement of the input failed, e.g. a filter given a group it cannot read
+    pub error: Option<String>,
+}
+
+impl ExampleResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.actual == self.expected
+    }
+}
+
+/// Run wide settings, and the mode and flags of every rule which does not set its own
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Defaults {
+    directory: Option<PathBuf>,
+    extensions: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    ignored_dirs: Vec<String>,
+    mode: Mode,
+    flags: Vec<Flag>,
+    multiline: bool,
+    rule_order: Order,
+    counter_scope: Scope,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    #[serde(default)]
+    name: Option<String>,
+    search: String,
+    #[serde(default)]
+    replace: String,
+    #[serde(default)]
+    mode: Option<Mode>,
+    #[serde(default)]
+    flags: Option<Vec<Flag>>,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    ignored_dirs: Vec<String>,
+    #[serde(default)]
+    examples: Vec<RuleExample>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Mode {
+    #[default]
+    Regex,
+    Literal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Flag {
+    IgnoreCase,
+    SmartCase,
+    WordRegexp,
+    LiteralReplace,
+    CaseSpans,
+    PreserveCase,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Order {
+    #[default]
+    Sequential,
+    Simultaneous,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Scope {
+    #[default]
+    Global,
+    File,
+    Rule,
+}
+
+/// Reads a TOML (`.toml`) or YAML (`.yaml`, `.yml`) rules file and validates all of it, including
+/// every search pattern and glob, before anything is searched. Every problem found is reported in
+/// a single [`SarError::RulesFile`], numbered by rule. A relative `directory` is relative to the
+/// directory of the rules file, without one the current directory is searched
+pub fn load_rules(path: &Path) -> Result<RulesFile, SarError> {
+    let fail = |errors: Vec<String>| SarError::RulesFile {
+        path: path.to_path_buf(),
+        errors,
+    };
+    let text: String =
+        std::fs::read_to_string(path).map_err(|error| fail(vec![error.to_string()]))?;
+    let extension: Option<&str> = path.extension().and_then(|extension| extension.to_str());
+    let value: Value = match extension {
+        Some("toml") => toml::from_str(&text).map_err(|error| error.to_string()),
+        Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|error| error.to_string()),
+        _ => Err(String::from(
+            "unknown format, the file name must end with .toml, .yaml or .yml",
+        )),
+    }
+    .map_err(|error| fail(vec![error.trim_end().to_string()]))?;
+    let (defaults, rules): (Defaults, Vec<NamedRule>) = parse(value).map_err(fail)?;
+    let directory: PathBuf = match defaults.directory {
+        // relative to the rules file, wherever sar is run from
+        Some(directory) => path.parent().unwrap_or(Path::new("")).join(directory),
+        None => PathBuf::from("."),
+    };
+    let mut exclude: Vec<String> = defaults.exclude;
+    // a rules file inside the searched directory must not rewrite itself
+    if let Some(relative_path) = std::fs::canonicalize(path)
+        .ok()
+        .zip(std::fs::canonicalize(&directory).ok())
+        .and_then(|(path, directory)| {
+            path.strip_prefix(directory)
+                .ok()
+                .and_then(|relative_path| relative_path.to_str().map(globset::escape))
+        })
+    {
+        exclude.push(relative_path);
+    }
+    let mut builder: ReplaceOptionsBuilder = ReplaceOptions::builder()
+        .directory(directory)
+        .file_extensions(defaults.extensions)
+        .include(defaults.include)
+        .exclude(exclude)
+        .ignored_dirs(defaults.ignored_dirs)
+        .multiline(defaults.multiline)
+        .rule_order(match defaults.rule_order {
+            Order::Sequential => RuleOrder::Sequential,
+            Order::Simultaneous => RuleOrder::Simultaneous,
+        })
+        .counter_scope(match defaults.counter_scope {
+            Scope::Global => CounterScope::Global,
+            Scope::File => CounterScope::File,
+            Scope::Rule => CounterScope::Rule,
+        });
+    let mut names: Vec<Option<String>> = Vec::new();
+    let mut examples: Vec<Vec<RuleExample>> = Vec::new();
+    for (name, rule, rule_examples) in rules {
+        builder = builder.rule(rule);
+        names.push(name);
+        examples.push(rule_examples);
+    }
+    Ok(RulesFile {
+        path: path.to_path_buf(),
+        options: builder,
+        names,
+        examples,
+    })
+}
+
+/// Runs the examples of every rule of `rules_file` through the same replacement as a run on disk,
+/// every rule on its own and regardless of its include and exclude globs. Returns the outcome of
+/// every example, in the order of the rules and their examples
+pub fn run_examples(rules_file: &RulesFile) -> Result<Vec<ExampleResult>, SarError> {
+    let options: ReplaceOptions = rules_file.options.clone().build();
+    let mut results: Vec<ExampleResult> = Vec::new();
+    for (index, (rule, examples)) in options.rules().iter().zip(&rules_file.examples).enumerate() {
+        let rule_options: ReplaceOptions = ReplaceOptions::builder()
+            .rule(rule.clone())
+            .multiline(options.multiline())
+            .build();
+        let matchers: Vec<Matcher> = build_matchers(&rule_options)?;
+        for (example_index, example) in examples.iter().enumerate() {
+            let lines: Vec<String> = example
+                .input
+                .split_inclusive('\n')
+                .map(String::from)
+                .collect();
+            let (actual, error): (String, Option<String>) = match replace_lines(
+                &rules_file.path,
+                &lines,
+                &matchers[0],
+                &rule_options,
+                &mut Counters::new(&rule_options),
+                |_| true,
+            ) {
+                Ok((actual, _)) => (actual, None),
+                Err(SarError::Replace { line, message, .. }) => (
+                    example.input.clone(),
+                    Some(format!("line {}: {}", line, message)),
+                ),
+                Err(error) => return Err(error),
+            };
+            results.push(ExampleResult {
+                rule: index,
+                example: example_index,
+                input: example.input.clone(),
+                expected: example.output.clone(),
+                actual,
+                error,
+            });
+        }
+    }
+    Ok(results)
+}
+
+/// Name of a rule, if it has one, the rule and its examples
+type NamedRule = (Option<String>, Rule, Vec<RuleExample>);
+
+/// Defaults and rules of a parsed rules file, or every problem found
+fn parse(value: Value) -> Result<(Defaults, Vec<NamedRule>), Vec<String>> {
+    let mut file: serde_json::Map<String, Value> = match value {
+        Value::Object(file) => file,
+        _ => {
+            return Err(vec![String::from(
+                "expected a table of version, defaults and rules",
+            )])
+        }
+    };
+    // a file of another version is not validated any further, its format is unknown
+    match file.remove("version") {
+        Some(Value::Number(version)) if version.as_u64() == Some(RULES_FILE_VERSION) => (),
+        Some(version) => {
+            return Err(vec![format!(
+                "version {} is not supported, the supported version is {}",
+                version, RULES_FILE_VERSION
+            )])
+        }
+        None => {
+            return Err(vec![format!(
+                "version is missing, add: version = {}",
+                RULES_FILE_VERSION
+            )])
+        }
+    }
+    let mut errors: Vec<String> = Vec::new();
+    let defaults: Defaults = match file.remove("defaults") {
+        Some(defaults) => Defaults::deserialize(defaults).unwrap_or_else(|error| {
+            errors.push(format!("defaults: {}", error));
+            Defaults::default()
+        }),
+        None => Defaults::default(),
+    };
+    if !validate_file_extensions(&defaults.extensions) {
+        errors.push(String::from(
+            "defaults: extensions cannot contain '*' and cannot start with '.'",
+        ));
+    }
+    if let Err(error) = FileFilter::build(&defaults.include, &defaults.exclude, &[]) {
+        errors.push(format!("defaults: {}", error));
+    }
+    let specs: Vec<Value> = match file.remove("rules") {
+        Some(Value::Array(specs)) if !specs.is_empty() => specs,
+        Some(Value::Array(_)) | None => {
+            errors.push(String::from("rules: at least one rule is required"));
+            Vec::new()
+        }
+        Some(_) => {
+            errors.push(String::from("rules: expected a list of rules"));
+            Vec::new()
+        }
+    };
+    for key in file.keys() {
+        errors.push(format!(
+            "unknown key `{}`, expected version, defaults or rules",
+            key
+        ));
+    }
+    let mut rules: Vec<NamedRule> = Vec::new();
+    for (index, spec) in specs.into_iter().enumerate() {
+        let label: String = match spec.get("name").and_then(Value::as_str) {
+            Some(name) => format!("rule {} ({})", index + 1, name),
+            None => format!("rule {}", index + 1),
+        };
+        let spec: RuleSpec = match RuleSpec::deserialize(spec) {
+            Ok(spec) => spec,
+            Err(error) => {
+                errors.push(format!("{}: {}", label, error));
+                continue;
+            }
+        };
+        if spec.search.is_empty() {
+            errors.push(format!("{}: search cannot be blank", label));
+            continue;
+        }
+        let rule: Rule = build_rule(&spec, &defaults);
+        // every rule is compiled on its own, so an error names the rule
+        let options: ReplaceOptions = ReplaceOptions::builder()
+            .rule(rule.clone())
+            .multiline(defaults.multiline)
+            .build();
+        if let Err(error) = build_matchers(&options) {
+            errors.push(format!("{}: {}", label, error));
+        }
+        rules.push((spec.name, rule, spec.examples));
+    }
+    if errors.is_empty() {
+        Ok((defaults, rules))
+    } else {
+        Err(errors)
+    }
+}
+
+fn build_rule(spec: &RuleSpec, defaults: &Defaults) -> Rule {
+    let flags: &[Flag] = spec.flags.as_deref().unwrap_or(&defaults.flags);
+    Rule {
+        search: spec.search.clone(),
+        replace: spec.replace.clone(),
+        fixed_strings: spec.mode.unwrap_or(defaults.mode) == Mode::Literal,
+        literal_replace: flags.contains(&Flag::LiteralReplace),
+        case_spans: flags.contains(&Flag::CaseSpans),
+        ignore_case: flags.contains(&Flag::IgnoreCase),
+        smart_case: flags.contains(&Flag::SmartCase),
+        word_regexp: flags.contains(&Flag::WordRegexp),
+        preserve_case: flags.contains(&Flag::PreserveCase),
+        include: spec.include.clone(),
+        exclude: spec.exclude.clone(),
+        ignored_dirs: spec.ignored_dirs.clone(),
+        ..Rule::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::rules_file::parse;
+    use crate::core::Rule;
+    use serde_json::Value;
+
+    fn toml(text: &str) -> Value {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn parse_applies_defaults_to_rules() {
+        let (defaults, rules) = parse(toml(
+            r#"
+            version = 1
+            [defaults]
+            ignored_dirs = ["node_modules"]
+            mode = "literal"
+            flags = ["ignore-case"]
+            rule_order = "simultaneous"
+            counter_scope = "rule"
+
+            [[rules]]
+            name = "rename"
+            search = "a.b"
+            replace = "c"
+
+            [[rules]]
+            search = '\bid\b'
+            mode = "regex"
+            flags = []
+            include = ["src/**"]
+            "#,
+        ))
+        .unwrap();
+        assert_eq!(defaults.ignored_dirs, vec!["node_modules"]);
+        assert_eq!(defaults.rule_order, super::Order::Simultaneous);
+        assert_eq!(defaults.counter_scope, super::Scope::Rule);
+        assert_eq!(rules[0].0, Some(String::from("rename")));
+        assert_eq!(
+            rules[0].1,
+            Rule {
+                fixed_strings: true,
+                ignore_case: true,
+                ..Rule::new("a.b", "c")
+            }
+        );
+        assert_eq!(
+            rules[1].1,
+            Rule {
+                include: vec![String::from("src/**")],
+                ..Rule::new(r"\bid\b", "")
+            }
+        );
+    }
+
+    #[test]
+    fn parse_reports_every_error_by_rule() {
+        let errors: Vec<String> = parse(toml(
+            r#"
+            version = 1
+            extra = true
+            [defaults]
+            include = ["src/[a"]
+
+            [[rules]]
+            search = "ok"
+
+            [[rules]]
+            name = "broken"
+            search = "a(b"
+
+            [[rules]]
+            search = "x"
+            flags = ["multiline"]
+
+            [[rules]]
+            search = ""
+            "#,
+        ))
+        .unwrap_err();
+        assert_eq!(errors.len(), 5);
+        assert!(errors[0].starts_with("defaults: Invalid glob \"src/[a\""));
+        assert_eq!(
+            errors[1],
+            "unknown key `extra`, expected version, defaults or rules"
+        );
+        assert!(errors[2].starts_with("rule 2 (broken): Invalid search pattern \"a(b\""));
+        assert!(errors[3].starts_with("rule 3: unknown variant `multiline`"));
+        assert_eq!(errors[4], "rule 4: search cannot be blank");
+    }
+
+    #[test]
+    fn parse_checks_version_first() {
+        let errors: Vec<String> = parse(toml("version = 2\nrules = 1")).unwrap_err();
+        assert_eq!(
+            errors,
+            vec!["version 2 is not supported, the supported version is 1"]
+        );
+        let errors: Vec<String> = parse(toml("rules = []")).unwrap_err();
+        assert_eq!(errors, vec!["version is missing, add: version = 1"]);
+    }
+}
@@ -0,0 +1,175 @@
+use super::SarError;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Operations a script may run for a single match, a loop which never ends is stopped after them
+pub(crate) const SCRIPT_MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A Rhai replace script, compiled once per rule and run for every match. The engine has no
+/// module resolver and Rhai has no file or network functions, so a script only sees what it is
+/// given: `captures` (the whole match, then every group, `()` for a group which did not match),
+/// `groups` (the named groups), `path` and `line`. The value of the script is the replacement, a
+/// string, or `()` to leave the match alone. `print` and `debug` are disabled, their output would
+/// mix with the report or the terminal UI
+#[derive(Debug)]
+pub(crate) struct Script {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+}
+
+/// What a script is given for a match
+pub(crate) struct ScriptInput<'a> {
+    pub(crate) captures: Vec<Option<&'a str>>,
+    pub(crate) groups: Vec<(&'a str, &'a str)>,
+    pub(crate) path: &'a Path,
+    pub(crate) line: usize,
+}
+
+impl Script {
+    /// Fails if the script cannot be read or does not compile, before anything is searched
+    pub(crate) fn load(path: &Path) -> Result<Script, SarError> {
+        let fail = |message: String| SarError::Script {
+            path: path.to_path_buf(),
+            message,
+        };
+        let source: String = fs::read_to_string(path).map_err(|error| fail(error.to_string()))?;
+        let mut engine: Engine = Engine::new();
+        engine
+            .set_module_resolver(DummyModuleResolver::new())
+            .set_max_operations(SCRIPT_MAX_OPERATIONS)
+            .set_max_call_levels(64)
+            .set_max_string_size(1 << 20)
+            .set_max_array_size(1 << 16)
+            .set_max_map_size(1 << 16)
+            .disable_symbol("print")
+            .disable_symbol("debug");
+        let ast: AST = engine
+            .compile(source)
+            .map_err(|error| fail(error.to_string()))?;
+        Ok(Script {
+            path: path.to_path_buf(),
+            engine,
+            ast,
+        })
+    }
+
+    /// The replacement of a match, `None` to leave it alone. Fails if the script fails, runs out of
+    /// operations or returns something else than a string or `()`
+    pub(crate) fn run(&self, input: ScriptInput<'_>) -> Result<Option<String>, String> {
+        let captures: Array = input
+            .captures
+            .into_iter()
+            .map(|capture| {
+                capture
+                    .map(|capture| Dynamic::from(capture.to_string()))
+                    .unwrap_or(Dynamic::UNIT)
+            })
+            .collect();
+        let groups: Map = input
+            .groups
+            .into_iter()
+            .map(|(name, text)| (name.into(), Dynamic::from(text.to_string())))
+            .collect();
+        let mut scope: Scope<'_> = Scope::new();
+        scope
+            .push_constant("captures", captures)
+            .push_constant("groups", groups)
+            .push_constant("path", input.path.display().to_string())
+            .push_constant("line", input.line as i64);
+        let value: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|error| match *error {
+                EvalAltResult::ErrorTooManyOperations(_) => format!(
+                    "replace script {} ran more than {} operations",
+                    self.path.display(),
+                    SCRIPT_MAX_OPERATIONS
+                ),
+                error => format!("replace script {} failed: {}", self.path.display(), error),
+            })?;
+        if value.is_unit() {
+            return Ok(None);
+        }
+        let type_name: &str = value.type_name();
+        value.into_string().map(Some).map_err(|_| {
+            format!(
+                "replace script {} returned {}, expected a string or ()",
+                self.path.display(),
+                type_name
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::script::{Script, ScriptInput};
+    use crate::core::SarError;
+    use std::io::Write;
+    use std::path::Path;
+    use tempfile::NamedTempFile;
+
+    fn script(name: &str, source: &str) -> Result<Script, SarError> {
+        let mut file: NamedTempFile = tempfile::Builder::new()
+            .prefix(&format!("sar-script-{}-", name))
+            .suffix(".rhai")
+            .tempfile()
+            .unwrap();
+        file.write_all(source.as_bytes()).unwrap();
+        Script::load(file.path())
+    }
+
+    fn run(script: &Script, captures: &[Option<&str>]) -> Result<Option<String>, String> {
+        script.run(ScriptInput {
+            captures: captures.to_vec(),
+            groups: vec![("word", captures[0].unwrap_or_default())],
+            path: Path::new("src/a.txt"),
+            line: 7,
+        })
+    }
+
+    #[test]
+    fn script_returns_replacement_or_unit() {
+        let lookup: Script = script(
+            "lookup",
+            r##"
+                let codes = #{ red: "#f00", green: "#0f0" };
+                if groups.word in codes { codes[groups.word] }
+                else if captures[1] == () { `${path}:${line}` }
+            "##,
+        )
+        .unwrap();
+        assert_eq!(run(&lookup, &[Some("red")]), Ok(Some(String::from("#f00"))));
+        assert_eq!(run(&lookup, &[Some("blue"), Some("x")]), Ok(None));
+        assert_eq!(
+            run(&lookup, &[Some("blue"), None]),
+            Ok(Some(String::from("src/a.txt:7")))
+        );
+    }
+
+    #[test]
+    fn script_is_sandboxed() {
+        let endless: Script = script("endless", "loop {}").unwrap();
+        assert!(run(&endless, &[Some("a")])
+            .unwrap_err()
+            .ends_with("ran more than 1000000 operations"));
+        let number: Script = script("number", "42").unwrap();
+        assert!(run(&number, &[Some("a")])
+            .unwrap_err()
+            .ends_with("returned i64, expected a string or ()"));
+        let import: Script = script("import", r#"import "other" as other; "x""#).unwrap();
+        assert!(run(&import, &[Some("a")]).is_err());
+        // output of a script would mix with the report
+        match script("print", r#"print("x"); "y""#) {
+            Err(SarError::Script { .. }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match script("invalid", "let = 1;") {
+            Err(SarError::Script { .. }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,748 @@
+use super::case;
+use super::SarError;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use semver::Version;
+use std::borrow::Cow;
+
+/// Characters `urlencode` leaves as is, the unreserved characters of RFC 3986
+const URL_UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Replace text parsed once per rule. Besides the syntax of the regex crate (`$1`, `$name`,
+/// `${name}` and `$$`) a group can be followed by filters, e.g. `${name|upper}` or
+/// `${1|add:1|pad:3:0}`. With case spans `\U...\E` or `\L...\E` change the case of everything in
+/// between, `\\` is kept as two backslashes and only keeps `\\U` and `\\L` from starting a span.
+/// Any other backslash is inserted as is. `${counter}` and `${file_counter}` are a [`Counter`]
+/// unless the search text has a group of that name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Group {
+        group: Group,
+        /// Every filter with its text, e.g. `pad:4`
+        filters: Vec<(String, Filter)>,
+    },
+    Counter {
+        counter: Counter,
+        filters: Vec<(String, Filter)>,
+    },
+    /// `\U` or `\L`, `None` for `\E`
+    Case(Option<CaseSpan>),
+}
+
+/// `${counter}`, the number of a match, or `${file_counter}`, the number of its file, see
+/// [`Counters`](super::counter::Counters). Both count from `start` by `step`, e.g.
+/// `${counter:start=100,step=10}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Counter {
+    pub(crate) kind: CounterKind,
+    start: i64,
+    step: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CounterKind {
+    Match,
+    File,
+}
+
+/// Group referenced by a replace text, by index or by name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Group {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaseSpan {
+    Upper,
+    Lower,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Filter {
+    Upper,
+    Lower,
+    /// First letter of every word uppercase, the other letters lowercase
+    Title,
+    Trim,
+    /// Pads on the left up to `width` characters
+    Pad {
+        width: usize,
+        fill: char,
+    },
+    UrlEncode,
+    /// Adds to an integer
+    Add(i64),
+    /// Bumps a semantic version, a leading `v` is kept and build metadata is dropped. A pre-release
+    /// is bumped to its release if the lower numbers are already 0, e.g. the patch of 1.2.3-rc.1
+    /// is 1.2.3 and its minor 1.3.0
+    SemverBump(Bump),
+    /// Formats a number like printf
+    Format(NumberFormat),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bump {
+    Major,
+    Minor,
+    Patch,
+}
+
+/// A single printf conversion of a number, with the text around it, e.g. `#%04d`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NumberFormat {
+    prefix: String,
+    suffix: String,
+    /// `-` flag
+    left: bool,
+    /// `+` flag
+    plus: bool,
+    /// `0` flag
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    /// One of `d`, `x`, `X`, `o` and `f`
+    conversion: char,
+}
+
+impl Template {
+    /// A replace text inserted as is
+    pub(crate) fn literal(replace: &str) -> Template {
+        Template {
+            parts: vec![Part::Literal(replace.to_string())],
+        }
+    }
+
+    /// Fails on an unknown filter or a filter argument which is not valid, before anything is
+    /// searched. `groups` are the names of the groups of the search text. Backslashes are inserted
+    /// as is, unless `case_spans` is set: then `\U` and `\L` start a case span and `\E` ends it
+    pub(crate) fn parse(
+        replace: &str,
+        groups: &[&str],
+        case_spans: bool,
+    ) -> Result<Template, SarError> {
+        let fail = |message: String| SarError::InvalidTemplate {
+            template: replace.to_string(),
+            message,
+        };
+        let mut parts: Vec<Part> = Vec::new();
+        let mut literal: String = String::new();
+        let mut rest: &str = replace;
+        let mut case_open: bool = false;
+        let special: &[char] = if case_spans { &['$', '\\'] } else { &['$'] };
+        while let Some(index) = rest.find(special) {
+            literal.push_str(&rest[..index]);
+            let after: &str = &rest[index + 1..];
+            if rest[index..].starts_with('\\') {
+                let case: Option<CaseSpan> = match after.chars().next() {
+                    Some('U') => Some(CaseSpan::Upper),
+                    Some('L') => Some(CaseSpan::Lower),
+                    // an \E which ends no case span is not special, like any other backslash
+                    Some('E') if case_open => None,
+                    // \\ is kept, so that \\U is not a case span
+                    Some('\\') => {
+                        literal.push_str("\\\\");
+                        rest = &after[1..];
+                        continue;
+                    }
+                    _ => {
+                        literal.push('\\');
+                        rest = after;
+                        continue;
+                    }
+                };
+                case_open = case.is_some();
+                push_literal(&mut parts, &mut literal);
+                parts.push(Part::Case(case));
+                rest = &after[1..];
+                continue;
+            }
+            if let Some(after) = after.strip_prefix('$') {
+                literal.push('$');
+                rest = after;
+                continue;
+            }
+            let (reference, after): (&str, &str) = match after.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(end) => (&braced[..end], &braced[end + 1..]),
+                    None => {
+                        literal.push('$');
+                        rest = after;
+                        continue;
+                    }
+                },
+                None => {
+                    let end: usize = after
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .unwrap_or(after.len());
+                    if end == 0 {
+                        literal.push('$');
+                        rest = after;
+                        continue;
+                    }
+                    (&after[..end], &after[end..])
+                }
+            };
+            let mut pieces = reference.split('|');
+            let name: &str = pieces.next().unwrap_or_default();
+            let filters: Vec<(String, Filter)> = pieces
+                .map(|filter| {
+                    Filter::parse(filter)
+                        .map(|parsed| (filter.to_string(), parsed))
+                        .map_err(fail)
+                })
+                .collect::<Result<_, _>>()?;
+            push_literal(&mut parts, &mut literal);
+            let (base, arguments): (&str, Option<&str>) = match name.split_once(':') {
+                Some((base, arguments)) => (base, Some(arguments)),
+                None => (name, None),
+            };
+            let kind: Option<CounterKind> = match base {
+                _ if groups.contains(&name) => None,
+                "counter" => Some(CounterKind::Match),
+                "file_counter" => Some(CounterKind::File),
+                _ => None,
+            };
+            parts.push(match kind {
+                Some(kind) => Part::Counter {
+                    counter: Counter::parse(kind, arguments)
+                        .ok_or_else(|| {
+                            format!(
+                                "`{}` expects start=N and step=N, e.g. {}:start=100,step=10",
+                                name, base
+                            )
+                        })
+                        .map_err(fail)?,
+                    filters,
+                },
+                None => Part::Group {
+                    group: match name.parse::<usize>() {
+                        Ok(index) => Group::Index(index),
+                        Err(_) => Group::Name(name.to_string()),
+                    },
+                    filters,
+                },
+            });
+            rest = after;
+        }
+        literal.push_str(rest);
+        push_literal(&mut parts, &mut literal);
+        Ok(Template { parts })
+    }
+
+    /// True if a counter of `kind` is part of the replacement
+    pub(crate) fn uses(&self, kind: CounterKind) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Counter { counter, .. } if counter.kind == kind))
+    }
+
+    /// The replacement of a match, `group` returns the text of a group or `None` if the group did
+    /// not match or does not exist. Such a group is a blank ("") value. `counted` returns how many
+    /// matches or files were numbered before this one. Fails if a filter cannot read a group, e.g.
+    /// `add` a group which is not an integer
+    pub(crate) fn expand<'a, F, C>(&self, group: F, counted: C) -> Result<String, String>
+    where
+        F: Fn(&Group) -> Option<&'a str>,
+        C: Fn(CounterKind) -> u64,
+    {
+        let mut expanded: String = String::new();
+        let mut case: Option<CaseSpan> = None;
+        for part in &self.parts {
+            let text: Cow<'_, str> = match part {
+                Part::Literal(text) => Cow::Borrowed(text.as_str()),
+                Part::Group {
+                    group: reference,
+                    filters,
+                } => apply_filters(Cow::Borrowed(group(reference).unwrap_or("")), filters)?,
+                Part::Counter { counter, filters } => {
+                    let value: i64 = i64::try_from(counted(counter.kind))
+                        .ok()
+                        .and_then(|counted| counter.step.checked_mul(counted))
+                        .and_then(|offset| counter.start.checked_add(offset))
+                        .ok_or_else(|| String::from("counter overflows"))?;
+                    apply_filters(Cow::Owned(value.to_string()), filters)?
+                }
+                Part::Case(span) => {
+                    case = *span;
+                    continue;
+                }
+            };
+            match case {
+                Some(CaseSpan::Upper) => expanded.push_str(&text.to_uppercase()),
+                Some(CaseSpan::Lower) => expanded.push_str(&text.to_lowercase()),
+                None => expanded.push_str(&text),
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+fn apply_filters<'a>(
+    value: Cow<'a, str>,
+    filters: &[(String, Filter)],
+) -> Result<Cow<'a, str>, String> {
+    let mut value: Cow<'a, str> = value;
+    for (text, filter) in filters {
+        value = Cow::Owned(
+            filter
+                .apply(&value)
+                .map_err(|error| format!("filter `{}` {}", text, error))?,
+        );
+    }
+    Ok(value)
+}
+
+fn push_literal(parts: &mut Vec<Part>, literal: &mut String) {
+    if !literal.is_empty() {
+        parts.push(Part::Literal(std::mem::take(literal)));
+    }
+}
+
+impl Counter {
+    /// `arguments` are `start=N` and `step=N` separated by commas, both 1 if not given
+    fn parse(kind: CounterKind, arguments: Option<&str>) -> Option<Counter> {
+        let mut counter: Counter = Counter {
+            kind,
+            start: 1,
+            step: 1,
+        };
+        for argument in arguments
+            .into_iter()
+            .flat_map(|arguments| arguments.split(','))
+        {
+            let (key, value): (&str, &str) = argument.split_once('=')?;
+            let value: i64 = value.trim().parse().ok()?;
+            match key.trim() {
+                "start" => counter.start = value,
+                "step" => counter.step = value,
+                _ => return None,
+            }
+        }
+        Some(counter)
+    }
+}
+
+impl Filter {
+    fn parse(filter: &str) -> Result<Filter, String> {
+        let (name, argument): (&str, Option<&str>) = match filter.split_once(':') {
+            Some((name, argument)) => (name.trim(), Some(argument)),
+            None => (filter.trim(), None),
+        };
+        let arguments: Vec<&str> = argument
+            .map(|argument| argument.split(':').collect())
+            .unwrap_or_default();
+        let filter: Filter = match name {
+            "upper" => Filter::Upper,
+            "lower" => Filter::Lower,
+            "title" => Filter::Title,
+            "trim" => Filter::Trim,
+            "urlencode" => Filter::UrlEncode,
+            "pad" => {
+                let width: Option<usize> = arguments.first().and_then(|width| width.parse().ok());
+                let fill: Option<char> = match arguments.get(1) {
+                    Some(fill) => {
+                        let mut chars = fill.chars();
+                        chars.next().filter(|_| chars.next().is_none())
+                    }
+                    None => Some(' '),
+                };
+                return match (width, fill, arguments.len()) {
+                    (Some(width), Some(fill), 1 | 2) => Ok(Filter::Pad { width, fill }),
+                    _ => Err(format!(
+                        "filter `{}` expects a width and an optional fill character, e.g. pad:4 or pad:4:0",
+                        filter
+                    )),
+                };
+            }
+            "add" => {
+                return match argument.map(|amount| amount.trim().parse::<i64>()) {
+                    Some(Ok(amount)) => Ok(Filter::Add(amount)),
+                    _ => Err(format!(
+                        "filter `{}` expects an integer to add, e.g. add:1 or add:-1",
+                        filter
+                    )),
+                };
+            }
+            "semver_bump" => {
+                return match argument.map(str::trim) {
+                    Some("major") => Ok(Filter::SemverBump(Bump::Major)),
+                    Some("minor") => Ok(Filter::SemverBump(Bump::Minor)),
+                    Some("patch") => Ok(Filter::SemverBump(Bump::Patch)),
+                    _ => Err(format!(
+                        "filter `{}` expects major, minor or patch, e.g. semver_bump:minor",
+                        filter
+                    )),
+                };
+            }
+            "format" => {
+                return argument
+                    .and_then(NumberFormat::parse)
+                    .map(Filter::Format)
+                    .ok_or_else(|| {
+                        format!(
+                            "filter `{}` expects a single %d, %x, %X, %o or %f conversion, e.g. format:%04d",
+                            filter
+                        )
+                    });
+            }
+            _ => {
+                return Err(format!(
+                    "unknown filter `{}`, expected upper, lower, title, trim, pad, urlencode, add, semver_bump or format",
+                    name
+                ))
+            }
+        };
+        if arguments.is_empty() {
+            Ok(filter)
+        } else {
+            Err(format!("filter `{}` takes no argument", name))
+        }
+    }
+
+    fn apply(&self, value: &str) -> Result<String, String> {
+        Ok(match self {
+            Filter::Upper => value.to_uppercase(),
+            Filter::Lower => value.to_lowercase(),
+            Filter::Title => case::title_words(value),
+            Filter::Trim => value.trim().to_string(),
+            Filter::Pad { width, fill } => {
+                let length: usize = value.chars().count();
+                let mut padded: String = std::iter::repeat(*fill)
+                    .take(width.saturating_sub(length))
+                    .collect();
+                padded.push_str(value);
+                padded
+            }
+            Filter::UrlEncode => utf8_percent_encode(value, URL_UNRESERVED).to_string(),
+            Filter::Add(amount) => parse_integer(value)?
+                .checked_add(*amount)
+                .ok_or_else(|| format!("overflows adding to \"{}\"", value))?
+                .to_string(),
+            Filter::SemverBump(bump) => {
+                let (prefix, version): (&str, &str) = match value.strip_prefix('v') {
+                    Some(version) => ("v", version),
+                    None => ("", value),
+                };
+                let version: Version = Version::parse(version).map_err(|_| {
+                    format!("expects a semantic version like 1.2.3, found \"{}\"", value)
+                })?;
+                // the release of a pre-release is the next version when the lower numbers are 0
+                let release = |lower: &[u64]| -> u64 {
+                    if !version.pre.is_empty() && lower.iter().all(|number| *number == 0) {
+                        0
+                    } else {
+                        1
+                    }
+                };
+                let bumped: Version = match bump {
+                    Bump::Major => Version::new(
+                        version.major + release(&[version.minor, version.patch]),
+                        0,
+                        0,
+                    ),
+                    Bump::Minor => {
+                        Version::new(version.major, version.minor + release(&[version.patch]), 0)
+                    }
+                    Bump::Patch => {
+                        Version::new(version.major, version.minor, version.patch + release(&[]))
+                    }
+                };
+                format!("{}{}", prefix, bumped)
+            }
+            Filter::Format(format) => format.apply(value)?,
+        })
+    }
+}
+
+fn parse_integer(value: &str) -> Result<i64, String> {
+    value
+        .parse::<i64>()
+        .map_err(|_| format!("expects an integer, found \"{}\"", value))
+}
+
+impl NumberFormat {
+    /// `None` unless `format` has exactly one conversion, `%%` is a `%`
+    fn parse(format: &str) -> Option<NumberFormat> {
+        let mut prefix: String = String::new();
+        let mut suffix: String = String::new();
+        let mut conversion: Option<NumberFormat> = None;
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            let text: &mut String = if conversion.is_some() {
+                &mut suffix
+            } else {
+                &mut prefix
+            };
+            if c != '%' {
+                text.push(c);
+                continue;
+            }
+            if chars.peek() == Some(&'%') {
+                chars.next();
+                text.push('%');
+                continue;
+            }
+            if conversion.is_some() {
+                return None;
+            }
+            let mut spec: NumberFormat = NumberFormat {
+                prefix: String::new(),
+                suffix: String::new(),
+                left: false,
+                plus: false,
+                zero: false,
+                width: 0,
+                precision: None,
+                conversion: 'd',
+            };
+            while let Some(flag) = chars.next_if(|c| matches!(c, '-' | '+' | '0')) {
+                match flag {
+                    '-' => spec.left = true,
+                    '+' => spec.plus = true,
+                    _ => spec.zero = true,
+                }
+            }
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                spec.width = spec.width * 10 + digit.to_digit(10)? as usize;
+            }
+            if chars.next_if_eq(&'.').is_some() {
+                let mut precision: usize = 0;
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    precision = precision * 10 + digit.to_digit(10)? as usize;
+                }
+                spec.precision = Some(precision);
+            }
+            spec.conversion = chars.next().filter(|c| "dxXof".contains(*c))?;
+            if spec.precision.is_some() && spec.conversion != 'f' {
+                return None;
+            }
+            conversion = Some(spec);
+        }
+        let mut format: NumberFormat = conversion?;
+        format.prefix = prefix;
+        format.suffix = suffix;
+        Some(format)
+    }
+
+    fn apply(&self, value: &str) -> Result<String, String> {
+        let (negative, digits): (bool, String) = if self.conversion == 'f' {
+            let number: f64 = value
+                .parse::<f64>()
+                .ok()
+                .filter(|number| number.is_finite())
+                .ok_or_else(|| format!("expects a number, found \"{}\"", value))?;
+            (
+                number.is_sign_negative() && number != 0.0,
+                format!("{:.*}", self.precision.unwrap_or(6), number.abs()),
+            )
+        } else {
+            let number: i64 = parse_integer(value)?;
+            let magnitude: u64 = number.unsigned_abs();
+            let digits: String = match self.conversion {
+                'x' => format!("{:x}", magnitude),
+                'X' => format!("{:X}", magnitude),
+                'o' => format!("{:o}", magnitude),
+                _ => magnitude.to_string(),
+            };
+            (number < 0, digits)
+        };
+        let sign: &str = match (negative, self.plus) {
+            (true, _) => "-",
+            (false, true) => "+",
+            (false, false) => "",
+        };
+        let padding: usize = self
+            .width
+            .saturating_sub(sign.chars().count() + digits.chars().count());
+        let number: String = if self.left {
+            format!("{}{}{}", sign, digits, " ".repeat(padding))
+        } else if self.zero {
+            format!("{}{}{}", sign, "0".repeat(padding), digits)
+        } else {
+            format!("{}{}{}", " ".repeat(padding), sign, digits)
+        };
+        Ok(format!("{}{}{}", self.prefix, number, self.suffix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::template::{CounterKind, Group, Template};
+    use crate::core::SarError;
+
+    fn expand(replace: &str, groups: &[(&str, &str)]) -> String {
+        try_expand(replace, groups, false).unwrap()
+    }
+
+    fn try_expand(
+        replace: &str,
+        groups: &[(&str, &str)],
+        case_spans: bool,
+    ) -> Result<String, String> {
+        let names: Vec<&str> = groups.iter().map(|(name, _)| *name).collect();
+        Template::parse(replace, &names, case_spans)
+            .unwrap()
+            .expand(
+                |group| {
+                    let name: String = match group {
+                        Group::Index(index) => index.to_string(),
+                        Group::Name(name) => name.clone(),
+                    };
+                    groups
+                        .iter()
+                        .find(|(group, _)| *group == name)
+                        .map(|(_, text)| *text)
+                },
+                |kind| match kind {
+                    CounterKind::Match => 4,
+                    CounterKind::File => 1,
+                },
+            )
+    }
+
+    #[test]
+    fn expand_regex_crate_syntax() {
+        assert_eq!(
+            expand("$0-${0}-$$-$1-${name}-$-${", &[("0", "ab")]),
+            "ab-ab-$---$-${"
+        );
+        assert_eq!(expand("$1a ${1}a", &[("1", "x"), ("1a", "y")]), "y xa");
+    }
+
+    #[test]
+    fn expand_filters_and_case_spans() {
+        let groups: &[(&str, &str)] = &[
+            ("1", "  Hello "),
+            ("name", "order item"),
+            ("n", "7"),
+            ("path", "a b/ü?"),
+        ];
+        assert_eq!(
+            expand("${1|lower|trim}|${name|upper}|${name|title}", groups),
+            "hello|ORDER ITEM|Order Item"
+        );
+        assert_eq!(
+            expand("${n|pad:4}|${n|pad:3:0}|${path|urlencode}", groups),
+            "   7|007|a%20b%2F%C3%BC%3F"
+        );
+        assert_eq!(
+            try_expand(r"\U$name\E-\L${1|trim}X\E-C:\\dir\x\E", groups, true).unwrap(),
+            r"ORDER ITEM-hellox-C:\\dir\x\E"
+        );
+        assert_eq!(
+            try_expand(r"\U${name}s", groups, true).unwrap(),
+            "ORDER ITEMS"
+        );
+    }
+
+    #[test]
+    fn expand_keeps_backslashes_without_case_spans() {
+        let groups: &[(&str, &str)] = &[("1", "Elena")];
+        assert_eq!(
+            expand(r"C:\Users\$1\new\Ebook\\share", groups),
+            r"C:\Users\Elena\new\Ebook\\share"
+        );
+        assert_eq!(expand(r"\U$1\E", groups), r"\UElena\E");
+    }
+
+    #[test]
+    fn expand_number_and_semver_filters() {
+        let groups: &[(&str, &str)] = &[("1", "41"), ("n", "-7"), ("ver", "v1.4.2"), ("x", "1.5")];
+        assert_eq!(
+            expand(
+                "${1|add:1} ${n|add:10} ${ver|semver_bump:minor} ${ver|semver_bump:major}",
+                groups
+            ),
+            "42 3 v1.5.0 v2.0.0"
+        );
+        let pre_releases: &[(&str, &str)] = &[("1", "1.2.3-rc.1+build.5"), ("2", "2.0.0-beta")];
+        assert_eq!(
+            expand(
+                "${1|semver_bump:patch} ${1|semver_bump:minor} ${1|semver_bump:major} \
+                ${2|semver_bump:patch} ${2|semver_bump:minor} ${2|semver_bump:major}",
+                pre_releases
+            ),
+            "1.2.3 1.3.0 2.0.0 2.0.0 2.0.0 2.0.0"
+        );
+        assert_eq!(
+            expand(
+                "${1|format:%04d} ${n|format:%+05d} ${1|format:%-4d}|${1|format:0x%X} ${x|format:%.2f%%}",
+                groups
+            ),
+            "0041 -0007 41  |0x29 1.50%"
+        );
+        assert_eq!(
+            try_expand("${ver|add:1}", groups, false),
+            Err(String::from(
+                "filter `add:1` expects an integer, found \"v1.4.2\""
+            ))
+        );
+        assert_eq!(
+            try_expand("${1|semver_bump:patch}", groups, false),
+            Err(String::from(
+                "filter `semver_bump:patch` expects a semantic version like 1.2.3, found \"41\""
+            ))
+        );
+    }
+
+    #[test]
+    fn expand_counters() {
+        assert_eq!(
+            expand(
+                "${counter} $counter ${counter:start=100,step=10} ${counter:step=-1|format:%03d}",
+                &[]
+            ),
+            "5 5 140 -03"
+        );
+        assert_eq!(
+            expand("${file_counter:start=0}-${counter}", &[("counter", "c")]),
+            "1-c"
+        );
+        let template: Template = Template::parse("$file_counter", &[], false).unwrap();
+        assert!(template.uses(CounterKind::File) && !template.uses(CounterKind::Match));
+    }
+
+    #[test]
+    fn parse_reports_unknown_filters() {
+        for (replace, message) in [
+            (
+                "${1|shout}",
+                "unknown filter `shout`, expected upper, lower, title, trim, pad, urlencode, add, semver_bump or format",
+            ),
+            (
+                "${1|format:%d-%d}",
+                "filter `format:%d-%d` expects a single %d, %x, %X, %o or %f conversion, e.g. format:%04d",
+            ),
+            (
+                "${1|semver_bump:huge}",
+                "filter `semver_bump:huge` expects major, minor or patch, e.g. semver_bump:minor",
+            ),
+            ("${1|upper:2}", "filter `upper` takes no argument"),
+            (
+                "${counter:first=2}",
+                "`counter:first=2` expects start=N and step=N, e.g. counter:start=100,step=10",
+            ),
+            (
+                "${1|pad:x}",
+                "filter `pad:x` expects a width and an optional fill character, e.g. pad:4 or pad:4:0",
+            ),
+        ] {
+            match Template::parse(replace, &[], false) {
+                Err(SarError::InvalidTemplate {
+                    message: error, ..
+                }) => assert_eq!(error, message),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+    }
+}
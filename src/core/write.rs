@@ -0,0 +1,237 @@
+use filetime::FileTime;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Result, Write};
+use std::path::{Path, PathBuf};
+use tempfile::{NamedTempFile, TempPath};
+
+/// Writes `content` to a temp file next to `path`, fsyncs it and renames it over `path`.
+/// On any failure the temp file is removed and the original file is left untouched.
+/// Permissions, ownership and extended attributes (including ACLs) of the original file are carried
+/// over to the new file, timestamps only when `preserve_mtime` is set.
+pub(crate) fn write_file(content: &[u8], path: &Path, preserve_mtime: bool) -> Result<()> {
+    stage_file(content, path, preserve_mtime)?.commit()
+}
+
+/// Writes `content` back to `path` like [`write_file`], with the permissions, ownership and
+/// timestamps of `original`, the metadata of the file before it was replaced
+pub(crate) fn restore_file(content: &[u8], path: &Path, original: &Metadata) -> Result<()> {
+    stage(content, path, original.clone(), Some(original))?.commit()
+}
+
+/// New content of a file, written and fsynced to a closed temp file next to it, see [`write_file`]
+pub(crate) struct StagedFile {
+    temp_path: TempPath, // deleted on drop unless committed
+    path: PathBuf,
+    original: Metadata,
+}
+
+/// First half of [`write_file`], the original file is not touched until [`StagedFile::commit`].
+/// The temp file is closed, so any number of files can be staged at once
+pub(crate) fn stage_file(content: &[u8], path: &Path, preserve_mtime: bool) -> Result<StagedFile> {
+    let metadata: Metadata = fs::metadata(path)?;
+    let times: Option<Metadata> = preserve_mtime.then(|| metadata.clone());
+    stage(content, path, metadata, times.as_ref())
+}
+
+fn stage(
+    content: &[u8],
+    path: &Path,
+    original: Metadata,
+    times: Option<&Metadata>,
+) -> Result<StagedFile> {
+    let temp_file: NamedTempFile = tempfile::Builder::new()
+        .prefix(".sar-")
+        .suffix(".tmp")
+        .tempfile_in(parent_directory(path))?;
+    temp_file.as_file().write_all(content)?;
+    copy_metadata(path, &original, temp_file.as_file(), temp_file.path())?;
+    if let Some(times) = times {
+        filetime::set_file_handle_times(
+            temp_file.as_file(),
+            Some(FileTime::from_last_access_time(times)),
+            Some(FileTime::from_last_modification_time(times)),
+        )?;
+    }
+    temp_file.as_file().sync_all()?;
+    Ok(StagedFile {
+        temp_path: temp_file.into_temp_path(),
+        path: path.to_path_buf(),
+        original,
+    })
+}
+
+impl StagedFile {
+    /// Metadata of the file the staged file replaces, taken when it was staged
+    pub(crate) fn original(&self) -> &Metadata {
+        &self.original
+    }
+
+    /// Renames the temp file over the original file
+    pub(crate) fn commit(self) -> Result<()> {
+        self.temp_path
+            .persist(&self.path)
+            .map_err(|error| error.error)?;
+        sync_directory(parent_directory(&self.path));
+        Ok(())
+    }
+}
+
+fn parent_directory(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+#[cfg(unix)]
+fn copy_metadata(
+    original_path: &Path,
+    original: &Metadata,
+    file: &File,
+    file_path: &Path,
+) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::AsRawFd;
+    let chown = |uid: libc::uid_t| -> Result<()> {
+        // SAFETY: fchown only reads the descriptor, which `file` keeps open for the whole call
+        let result: libc::c_int = unsafe { libc::fchown(file.as_raw_fd(), uid, original.gid()) };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    };
+    // changing the owner needs privileges, a group writable file owned by another user is edited
+    // by its group members with their own ownership but keeps its group, -1 leaves the owner as is
+    match chown(original.uid()).or_else(|error| match error.raw_os_error() {
+        Some(libc::EPERM) => chown(libc::uid_t::MAX),
+        _ => Err(error),
+    }) {
+        Err(error) if error.raw_os_error() != Some(libc::EPERM) => return Err(error),
+        _ => (),
+    }
+    // extended attributes are best effort, some need privileges as well
+    if let Ok(names) = xattr::list(original_path) {
+        for name in names {
+            if let Ok(Some(value)) = xattr::get(original_path, &name) {
+                let _ = xattr::set(file_path, &name, &value);
+            }
+        }
+    }
+    // set after chown, which clears the setuid and setgid bits
+    fs::set_permissions(file_path, original.permissions())
+}
+
+#[cfg(not(unix))]
+fn copy_metadata(
+    _original_path: &Path,
+    original: &Metadata,
+    _file: &File,
+    file_path: &Path,
+) -> Result<()> {
+    fs::set_permissions(file_path, original.permissions())
+}
+
+/// Makes the rename durable. Best effort, not supported on every platform.
+#[cfg(unix)]
+fn sync_directory(directory: &Path) {
+    if let Ok(directory) = File::open(directory) {
+        let _ = directory.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_directory: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::write::write_file;
+    use filetime::FileTime;
+    use std::fs::{create_dir, metadata, read_dir, read_to_string, write};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn write_file_replaces_contents_without_leaving_temp_files() {
+        let directory: TempDir = tempfile::tempdir().unwrap();
+        let path: PathBuf = directory.path().join("test.txt");
+        write(&path, "positive\n").unwrap();
+        write_file(b"negative\n", &path, false).unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "negative\n");
+        assert_eq!(read_dir(directory.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn write_file_leaves_original_untouched_on_failure() {
+        let directory: TempDir = tempfile::tempdir().unwrap();
+        let path: PathBuf = directory.path().join("test");
+        create_dir(&path).unwrap();
+        write(path.join("test.txt"), "positive\n").unwrap();
+        assert!(write_file(b"negative\n", &path, false).is_err());
+        assert_eq!(read_to_string(path.join("test.txt")).unwrap(), "positive\n");
+        assert_eq!(read_dir(directory.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_file_preserves_permissions() {
+        use std::fs::{set_permissions, Permissions};
+        use std::os::unix::fs::PermissionsExt;
+        let directory: TempDir = tempfile::tempdir().unwrap();
+        let path: PathBuf = directory.path().join("test.sh");
+        write(&path, "echo positive\n").unwrap();
+        set_permissions(&path, Permissions::from_mode(0o754)).unwrap();
+        write_file(b"echo negative\n", &path, false).unwrap();
+        assert_eq!(
+            metadata(&path).unwrap().permissions().mode() & 0o7777,
+            0o754
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_file_preserves_group() {
+        use std::os::unix::fs::MetadataExt;
+        let directory: TempDir = tempfile::tempdir().unwrap();
+        let path: PathBuf = directory.path().join("test.txt");
+        write(&path, "positive\n").unwrap();
+        // any group for root, otherwise a group of the user other than the one new files get
+        let mut groups: Vec<libc::gid_t> = vec![0; 64];
+        // SAFETY: getgroups writes at most `groups.len()` ids into the buffer
+        let count: libc::c_int = unsafe { libc::getgroups(64, groups.as_mut_ptr()) };
+        // SAFETY: geteuid has no preconditions
+        let gid: Option<libc::gid_t> = match unsafe { libc::geteuid() } {
+            0 => Some(12345),
+            _ => groups[..count.max(0) as usize]
+                .iter()
+                .copied()
+                .find(|gid| *gid != metadata(&path).unwrap().gid()),
+        };
+        let gid: libc::gid_t = match gid {
+            Some(gid) => gid,
+            None => return,
+        };
+        std::os::unix::fs::chown(&path, None, Some(gid)).unwrap();
+        write_file(b"negative\n", &path, false).unwrap();
+        assert_eq!(metadata(&path).unwrap().gid(), gid);
+    }
+
+    #[test]
+    fn write_file_preserves_mtime_when_asked() {
+        let directory: TempDir = tempfile::tempdir().unwrap();
+        let path: PathBuf = directory.path().join("test.txt");
+        write(&path, "positive\n").unwrap();
+        let mtime: FileTime = FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(&path, mtime).unwrap();
+        write_file(b"negative\n", &path, true).unwrap();
+        assert_eq!(
+            FileTime::from_last_modification_time(&metadata(&path).unwrap()),
+            mtime
+        );
+        write_file(b"positive\n", &path, false).unwrap();
+        assert_ne!(
+            FileTime::from_last_modification_time(&metadata(&path).unwrap()),
+            mtime
+        );
+    }
+}
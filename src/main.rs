@@ -1,37 +1,236 @@
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use console::style;
 use dialoguer::Confirm;
 use sar::core::{
-    process_directory, validate_file_extensions, TOTAL_DIR_READ_ERROR, TOTAL_FILES_EDITED_OK,
-    TOTAL_FILES_PROCESS_ERROR, TOTAL_FILES_TO_BE_EDITED,
+    apply, build_matchers, default_state_dir, history, load_mapping, load_rules, process_directory,
+    process_directory_interactive, prune, run_examples, undo, validate_file_extensions, Backup,
+    CounterScope, Decision, ExampleResult, FileOutcome, FileReport, Journal, JournalRule,
+    MatchCandidate, MatchSpan, ReplaceOptions, ReplaceOptionsBuilder, Report, Rule, RuleOrder,
+    RulesFile, SarError, UndoOutcome, UndoReport,
 };
-use std::{path::PathBuf, sync::atomic::Ordering};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+mod tui;
 
 #[derive(Parser, Default, Debug)]
-#[clap(version, about)]
+#[clap(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Arguments {
+    #[clap(subcommand)]
+    command: Option<Command>,
     #[clap(short)]
     /// Directory to search for files recursively. If omitted, current directory (".") will be taken. Usage: -d "C:\Temp"
     directory: Option<PathBuf>,
     #[clap(multiple = true, short = 'x')]
     /// File extension(s) to include in the search. If omitted, all file extensions will be included. Usage: -x txt (single file extension) or -x json -x txt (multiple file extensions) or -x json txt (multiple file extensions)
     file_extensions: Vec<String>,
-    #[clap(multiple = true, short = 'i')]
-    /// Sub directory(s) to ignore in the search. Usage: -i node_modules (single directory) or -i node_modules -i target (multiple directories) or -i node_modules target (multiple directories)
+    #[clap(multiple = true, short = 'I', long = "ignore-dir")]
+    /// Sub directory(s) to ignore in the search. Usage: -I node_modules (single directory) or -I node_modules -I target (multiple directories) or -I node_modules target (multiple directories)
     ignored_dirs: Vec<String>,
-    #[clap(forbid_empty_values = true, short)]
-    /// Text to search in files, cannot be a blank ("") value. Supports regex. Usage: -s test
-    search: String,
-    #[clap(short)]
-    /// Text to replace in files. If omitted, blank ("") value will be taken. Supports regex. Usage: -r test
-    replace: Option<String>,
+    #[clap(
+        forbid_empty_values = true,
+        short,
+        visible_short_alias = 'e',
+        required_unless_present_any = &["map", "rename-identifier"],
+        multiple_occurrences = true
+    )]
+    /// Text to search in files, cannot be a blank ("") value. Supports regex. Repeat it with -e to apply several rules in a single walk, the n-th replace text belongs to the n-th search text. Usage: -s test or -e foo -r bar -e baz -r qux
+    search: Vec<String>,
+    #[clap(
+        long = "map",
+        conflicts_with_all = &["fixed-strings", "literal-replace", "tui"]
+    )]
+    /// Replace every key of a mapping file of old,new pairs (CSV, or TSV if named *.tsv, without a header) with its value, all keys in a single pass per file. The longest key wins, values are inserted as is. -i, -S and -w apply to the keys, -i only folds ASCII letters. Keys never found are reported. Usage: --map mapping.csv
+    map: Option<PathBuf>,
+    #[clap(
+        long = "rename-identifier",
+        number_of_values = 2,
+        value_names = &["OLD", "NEW"],
+        conflicts_with_all = &["fixed-strings", "literal-replace", "ignore-case", "smart-case", "word-regexp", "preserve-case", "tui"]
+    )]
+    /// Rename an identifier in every case style: camelCase, PascalCase, snake_case, kebab-case and SCREAMING_CASE, each replaced with the same style of the new name. Names are words separated by spaces, '_' or '-'. A match has to start and end at a word of an identifier, getOrderItem is renamed, reorder_item is not. Usage: --rename-identifier "order item" "line item"
+    rename_identifier: Vec<String>,
+    #[clap(long = "rule-order", value_enum, default_value = "sequential")]
+    /// How several rules are applied to each file. Sequential: one after the other, each rule sees the replacements of the rules before it. Simultaneous: every rule matches the original text, of overlapping matches the one starting first wins, then the rule given first. Usage: --rule-order simultaneous
+    rule_order: RuleOrderChoice,
+    #[clap(long = "counter-scope", value_enum, default_value = "global")]
+    /// What ${counter} in the replace text counts. Global: every replaced match of the run, files are walked sorted by name. File: starts over in every file. Rule: every rule counts its own matches. Usage: -r "ID-${counter:start=100,step=10}" --counter-scope file
+    counter_scope: CounterScopeChoice,
+    #[clap(short = 'F', long = "fixed-strings", action = ArgAction::SetTrue)]
+    /// Treat the search text as a literal string instead of a regex. Usage: -F -s "a.b(c)"
+    fixed_strings: bool,
+    #[clap(short = 'i', long = "ignore-case", action = ArgAction::SetTrue)]
+    /// Match the search text case insensitively. Usage: -i -s hello
+    ignore_case: bool,
+    #[clap(short = 'S', long = "smart-case", action = ArgAction::SetTrue)]
+    /// Match case insensitively if the search text has no uppercase letter, case sensitively otherwise. Usage: -S -s hello
+    smart_case: bool,
+    #[clap(short = 'w', long = "word-regexp", action = ArgAction::SetTrue)]
+    /// Match whole words only (Unicode aware word boundaries). Usage: -w -s id
+    word_regexp: bool,
+    #[clap(long = "preserve-case", action = ArgAction::SetTrue)]
+    /// Match the search text case insensitively and give every replacement the case of its match (lower, upper or title case), e.g. Widget becomes Gadget and WIDGET becomes GADGET. Usage: --preserve-case -s widget -r gadget
+    preserve_case: bool,
+    #[clap(short = 'U', long = "multiline", action = ArgAction::SetTrue)]
+    /// Match the search text against the whole file instead of line by line, so a match can span several lines. ^ and $ match at line boundaries, (?s) lets . match line endings. Usage: -U -s "foo,\n\s*bar"
+    multiline: bool,
+    #[clap(short, multiple_occurrences = true)]
+    /// Text to replace in files. If omitted, blank ("") value will be taken. Supports regex, filters on captured groups like ${1|upper} and, with --case-spans, \U...\E or \L...\E case spans. With several search texts every one needs its own replace text. Usage: -r test
+    replace: Vec<String>,
+    #[clap(long = "literal-replace", action = ArgAction::SetTrue)]
+    /// Insert the replace text as is, without expanding $name or ${name} to the captured groups. Usage: --literal-replace -r "$HOME"
+    literal_replace: bool,
+    #[clap(long = "case-spans", action = ArgAction::SetTrue, conflicts_with = "literal-replace")]
+    /// Let \U and \L in the replace text turn everything up to \E upper or lower case, like in sed and perl. Without it backslashes in the replace text are inserted as is, e.g. C:\Users. Usage: --case-spans -s "(\w+)_id" -r "\U$1\E_ID"
+    case_spans: bool,
+    #[clap(
+        long = "replace-script",
+        conflicts_with_all = &["replace", "literal-replace", "case-spans", "map", "rename-identifier", "tui"]
+    )]
+    /// Compute the replacement of every match with a Rhai script instead of a replace text. The script gets the constants captures (the whole match, then every group), groups (the named groups), path and line, and returns the replacement string, or () to leave the match alone. It cannot read files or use the network and may run 1,000,000 operations per match. Usage: --replace-script transform.rhai
+    replace_script: Option<PathBuf>,
+    #[clap(long = "color", value_enum, default_value = "auto", global = true)]
+    /// When to use colors. Auto uses colors only when the output is a terminal and the NO_COLOR environment variable is not set. Usage: --color=never
+    color: ColorChoice,
+    #[clap(flatten)]
+    run: RunArguments,
+    #[clap(long = "tui", action = ArgAction::SetTrue, conflicts_with = "interactive")]
+    /// Review the replacements in a full screen terminal UI before applying them: browse the files, compare each file side by side, toggle individual hunks (lines) and edit the search and replace text with a live preview. Usage: --tui
+    tui: bool,
+    #[clap(long = "yes", alias = "no-confirm", env = "SAR_YES", action = ArgAction::SetTrue, global = true)]
+    /// Skip the confirmation prompt. Required when stdin is not a terminal (CI jobs, git hooks, scripts). Can also be set with the SAR_YES environment variable. Usage: --yes or --no-confirm
+    yes: bool,
+}
+
+/// How a run edits the files and reports, shared by a search on the command line and `sar apply`
+#[derive(Args, Default, Debug)]
+struct RunArguments {
     #[clap(long = "dry", action = ArgAction::SetTrue)]
     /// Dry run option. No files will be modified. Just displays the files containing the search text. Usage: --dry
     dry_run: Option<bool>,
+    #[clap(long = "preserve-mtime", action = ArgAction::SetTrue)]
+    /// Keep the modification time of edited files, so build systems don't rebuild them. Usage: --preserve-mtime
+    preserve_mtime: bool,
+    #[clap(long = "no-journal", action = ArgAction::SetTrue)]
+    /// Don't write an undo journal, the run cannot be undone with sar undo. For environments where the state directory cannot be written, e.g. a read-only home directory in a container. Usage: --no-journal
+    no_journal: bool,
+    #[clap(
+        long = "backup-suffix",
+        conflicts_with = "backup-dir",
+        forbid_empty_values = true
+    )]
+    /// Keep a copy of every edited file next to it, named after the file plus the suffix. An existing copy is never overwritten, the new one is numbered (file.orig.1, file.orig.2, ...). Usage: --backup-suffix .orig
+    backup_suffix: Option<String>,
+    #[clap(long = "backup-dir")]
+    /// Keep a copy of every edited file in a mirror tree under the backup directory. An existing copy is never overwritten, the new one is numbered (file.1, file.2, ...). Usage: --backup-dir "C:\Backup"
+    backup_dir: Option<PathBuf>,
+    #[clap(long = "atomic", action = ArgAction::SetTrue)]
+    /// All or nothing option. Computes every replacement and stages every write first, edits the files only if all of them succeed. Files already edited are restored if a late step fails. Usage: --atomic
+    atomic: bool,
+    #[clap(long = "diff", action = ArgAction::SetTrue)]
+    /// Display a unified diff of the replacement for every file where the search text was found. Usage: --dry --diff
+    diff: bool,
+    #[clap(long = "context", default_value_t = 3)]
+    /// Number of context lines around every change in diffs, patch files and interactive prompts. Usage: --diff --context 5
+    context: usize,
+    #[clap(long = "patch-out")]
+    /// Write the replacement as a patch file instead of editing the files. The patch can be applied with git apply or patch -p1 from the searched directory. Usage: --patch-out changes.patch
+    patch_out: Option<PathBuf>,
+    #[clap(
+        long = "preview",
+        value_enum,
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "inline"
+    )]
+    /// Display every line where the search text was found, with its line number and the replacement either inline (old text struck through, new text in green) or as a before and after pair. Without --dry the lines are displayed before the confirmation prompt. Usage: --preview or --preview=before-after
+    preview: Option<PreviewStyle>,
+    #[clap(long = "interactive", action = ArgAction::SetTrue)]
+    /// Ask before replacing every match, showing it with the context lines around it. Answer y (replace), n (leave), a (replace all in this file), d (leave all in this file) or q (quit). Usage: --interactive
+    interactive: bool,
+}
+
+impl RunArguments {
+    /// A patch file is written instead of editing the files
+    fn is_dry_run(&self) -> bool {
+        self.dry_run.unwrap_or(false) || self.patch_out.is_some()
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum PreviewStyle {
+    Inline,
+    BeforeAfter,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum RuleOrderChoice {
+    #[default]
+    Sequential,
+    Simultaneous,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum CounterScopeChoice {
+    #[default]
+    Global,
+    File,
+    Rule,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum ColorChoice {
+    Always,
+    Never,
+    #[default]
+    Auto,
+}
+
+/// Every non dry run without --no-journal writes an undo journal to the state directory:
+/// SAR_STATE_DIR if set, otherwise $XDG_STATE_HOME/sar, ~/.local/state/sar or %LOCALAPPDATA%\sar
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Restore the files edited by a run. Files changed since the run are left untouched. If the run id is omitted, the latest run which was not undone yet will be taken. Usage: sar undo or sar undo 20221218T093512Z
+    Undo { run_id: Option<String> },
+    /// List past runs which can be undone. Usage: sar history or sar history --prune 10
+    History {
+        #[clap(long = "prune", value_name = "KEEP")]
+        /// Remove every run but the latest KEEP ones, with their copies of the original files
+        prune: Option<usize>,
+    },
+    /// Apply every rule of a rules file (TOML or YAML) in a single walk. The whole file is validated before any file is searched. Usage: sar apply rules.toml or sar apply rules.yaml --dry --diff
+    Apply {
+        /// Rules file, named *.toml, *.yaml or *.yml
+        rules: PathBuf,
+        #[clap(flatten)]
+        run: RunArguments,
+    },
+    /// Run the examples of every rule of a rules file and report the ones which fail, no file is searched. Usage: sar test rules.toml
+    Test {
+        /// Rules file, named *.toml, *.yaml or *.yml
+        rules: PathBuf,
+    },
 }
 
 fn main() {
     let separator = "=".repeat(60);
     let args: Arguments = Arguments::parse();
+    set_colors(args.color);
+    match args.command {
+        Some(Command::Undo { run_id }) => undo_run(run_id.as_deref(), args.yes, &separator),
+        Some(Command::History { prune }) => print_history(prune, &separator),
+        Some(Command::Apply { rules, run }) => apply_rules(&rules, &run, args.yes, &separator),
+        Some(Command::Test { rules }) => test_rules(&rules, &separator),
+        None => search_and_replace(args, &separator),
+    }
+}
+
+fn search_and_replace(args: Arguments, separator: &str) {
     let directory: PathBuf = args.directory.unwrap_or_else(|| PathBuf::from("."));
     let file_extensions: Vec<String> = args.file_extensions;
     let ignored_dirs: Vec<String> = args.ignored_dirs;
@@ -41,49 +240,818 @@ fn main() {
         println!("{}", separator);
         std::process::exit(1);
     }
-    let search: String = args.search;
-    let replace: String = args.replace.unwrap_or_else(|| String::from(""));
-    let dry_run: bool = args.dry_run.unwrap_or_else(|| false);
-    let message: String = format!("In directory: \"{}\", ignoring sub directory(s): {:?}, for file extension(s): {:?}, search for: \"{}\" and replace with: \"{}\" and dry-run: {}",
-    directory.display(), ignored_dirs, file_extensions, search, replace, dry_run);
+    if !args.replace.is_empty() && args.replace.len() != args.search.len() {
+        println!("{}", separator);
+        eprintln!(
+            "Every search text needs its own replace text, found {} search text(s) and {} replace text(s)",
+            args.search.len(),
+            args.replace.len()
+        );
+        println!("{}", separator);
+        std::process::exit(1);
+    }
+    let mut rules: Vec<(String, String)> = args
+        .search
+        .iter()
+        .enumerate()
+        .map(|(index, search)| {
+            let replace: String = args.replace.get(index).cloned().unwrap_or_default();
+            (search.clone(), replace)
+        })
+        .collect();
+    let (search, replace): (String, String) = if rules.is_empty() {
+        (String::new(), String::new())
+    } else {
+        rules.remove(0)
+    };
+    let map: Vec<(String, String)> = match &args.map {
+        Some(path) => match load_mapping(path) {
+            Ok(map) => map,
+            Err(error) => exit_with_error(&error, separator),
+        },
+        None => Vec::new(),
+    };
+    // rules of --map and --rename-identifier, applied after the search texts
+    let mut extra_texts: Vec<String> = Vec::new();
+    if let Some(path) = &args.map {
+        extra_texts.push(format!(
+            "replace the {} key(s) of mapping file: \"{}\"",
+            map.len(),
+            path.display()
+        ));
+    }
+    if let [old, new] = args.rename_identifier.as_slice() {
+        extra_texts.push(format!("rename identifier: \"{}\" to: \"{}\"", old, new));
+    }
+    let rule_order: RuleOrder = match args.rule_order {
+        RuleOrderChoice::Sequential => RuleOrder::Sequential,
+        RuleOrderChoice::Simultaneous => RuleOrder::Simultaneous,
+    };
+    let dry_run: bool = args.run.is_dry_run();
+    let rules_text: String = if let Some(path) = &args.replace_script {
+        let searches: Vec<String> = std::iter::once(&search)
+            .chain(rules.iter().map(|(search, _)| search))
+            .map(|search| format!("\"{}\"", search))
+            .collect();
+        format!(
+            "search for: {} and replace with script: \"{}\"",
+            searches.join(", "),
+            path.display()
+        )
+    } else if rules.is_empty() && extra_texts.is_empty() {
+        format!(
+            "search for: \"{}\" and replace with: \"{}\"",
+            search, replace
+        )
+    } else if search.is_empty() && extra_texts.len() == 1 {
+        extra_texts.remove(0)
+    } else {
+        let pairs: Vec<String> = std::iter::once((&search, &replace))
+            .filter(|(search, _)| !search.is_empty())
+            .chain(rules.iter().map(|(search, replace)| (search, replace)))
+            .map(|(search, replace)| format!("\"{}\" -> \"{}\"", search, replace))
+            .chain(extra_texts)
+            .enumerate()
+            .map(|(index, rule)| format!("{}. {}", index + 1, rule))
+            .collect();
+        format!(
+            "apply rules in {} order: {}",
+            order_name(rule_order),
+            pairs.join(", ")
+        )
+    };
+    let message: String = format!("In directory: \"{}\", ignoring sub directory(s): {:?}, for file extension(s): {:?}, {} and dry-run: {}",
+    directory.display(), ignored_dirs, file_extensions, rules_text, dry_run);
     let count: usize = message.chars().count();
     println!("{}", "=".repeat(count));
     println!("{}", message);
     println!("{}", "=".repeat(count));
-    if Confirm::new()
-        .with_prompt("Do you want to continue?")
-        .default(true)
-        .wait_for_newline(true)
-        .interact()
-        .expect("Error confirming user input")
-    {
-        process_directory(
-            directory.as_path(),
-            &file_extensions,
-            &ignored_dirs,
-            search.as_str(),
-            replace.as_str(),
-            &dry_run,
+    let mut builder: ReplaceOptionsBuilder = ReplaceOptions::builder()
+        .directory(directory)
+        .file_extensions(file_extensions)
+        .ignored_dirs(ignored_dirs)
+        .search(search.as_str())
+        .replace(replace.as_str())
+        .fixed_strings(args.fixed_strings)
+        .literal_replace(args.literal_replace)
+        .case_spans(args.case_spans)
+        .ignore_case(args.ignore_case)
+        .smart_case(args.smart_case)
+        .word_regexp(args.word_regexp)
+        .preserve_case(args.preserve_case)
+        .multiline(args.multiline)
+        .rule_order(rule_order)
+        .counter_scope(match args.counter_scope {
+            CounterScopeChoice::Global => CounterScope::Global,
+            CounterScopeChoice::File => CounterScope::File,
+            CounterScopeChoice::Rule => CounterScope::Rule,
+        });
+    if let Some(path) = &args.replace_script {
+        builder = builder.replace_script(path);
+    }
+    // the match options of the command line apply to every rule
+    for (search, replace) in rules {
+        builder = builder.rule(Rule {
+            search,
+            replace,
+            fixed_strings: args.fixed_strings,
+            literal_replace: args.literal_replace,
+            case_spans: args.case_spans,
+            ignore_case: args.ignore_case,
+            smart_case: args.smart_case,
+            word_regexp: args.word_regexp,
+            preserve_case: args.preserve_case,
+            script: args.replace_script.clone(),
+            ..Rule::default()
+        });
+    }
+    if let Some(path) = &args.map {
+        builder = builder.rule(Rule {
+            ignore_case: args.ignore_case,
+            smart_case: args.smart_case,
+            word_regexp: args.word_regexp,
+            preserve_case: args.preserve_case,
+            map,
+            map_file: Some(path.clone()),
+            ..Rule::default()
+        });
+    }
+    if let [old, new] = args.rename_identifier.as_slice() {
+        builder = builder.rule(Rule {
+            search: old.clone(),
+            replace: new.clone(),
+            identifier: Some((old.clone(), new.clone())),
+            ..Rule::default()
+        });
+    }
+    let builder: ReplaceOptionsBuilder = with_run_arguments(builder, &args.run);
+    let options: ReplaceOptions = builder.clone().build();
+    if let Err(error) = build_matchers(&options) {
+        exit_with_error(&error, separator);
+    }
+    if !args.tui {
+        return run(&builder, &args.run, args.yes, separator);
+    }
+    // applying in the terminal UI confirms the run
+    require_terminal(
+        "Standard input is not a terminal, cannot start the terminal UI",
+        separator,
+    );
+    let result: Result<Report, SarError> =
+        match tui::run(builder, search, replace, args.run.context) {
+            Ok(Some((options, review))) => apply(&options, &review),
+            Ok(None) => std::process::exit(0),
+            Err(error) => {
+                println!("{}", separator);
+                eprintln!("Error running the terminal UI: {}", error);
+                println!("{}", separator);
+                std::process::exit(1);
+            }
+        };
+    finish(result, &args.run, args.run.preview, separator);
+}
+
+fn apply_rules(rules: &Path, run_arguments: &RunArguments, yes: bool, separator: &str) {
+    let rules_file: RulesFile = match load_rules(rules) {
+        Ok(rules_file) => rules_file,
+        Err(error) => exit_with_error(&error, separator),
+    };
+    let builder: ReplaceOptionsBuilder = with_run_arguments(rules_file.options, run_arguments);
+    let options: ReplaceOptions = builder.clone().build();
+    let message: String = format!(
+        "Apply rules file: \"{}\", in directory: \"{}\", {} rule(s) in {} order and dry-run: {}",
+        rules.display(),
+        options.directory().display(),
+        options.rules().len(),
+        order_name(options.rule_order()),
+        options.dry_run()
+    );
+    let count: usize = message.chars().count();
+    println!("{}", "=".repeat(count));
+    println!("{}", message);
+    for (index, (rule, name)) in options.rules().iter().zip(&rules_file.names).enumerate() {
+        println!(
+            "  {}. {}\"{}\" -> \"{}\"",
+            index + 1,
+            name.as_ref()
+                .map(|name| format!("{}: ", name))
+                .unwrap_or_default(),
+            rule.search,
+            rule.replace
         );
-        println!("{}", separator);
+    }
+    println!("{}", "=".repeat(count));
+    run(&builder, run_arguments, yes, separator);
+}
+
+fn test_rules(rules: &Path, separator: &str) {
+    let rules_file: RulesFile = match load_rules(rules) {
+        Ok(rules_file) => rules_file,
+        Err(error) => exit_with_error(&error, separator),
+    };
+    let results: Vec<ExampleResult> = match run_examples(&rules_file) {
+        Ok(results) => results,
+        Err(error) => exit_with_error(&error, separator),
+    };
+    let message: String = format!(
+        "Test rules file: \"{}\", {} example(s) of {} rule(s)",
+        rules.display(),
+        results.len(),
+        rules_file.names.len()
+    );
+    let count: usize = message.chars().count();
+    println!("{}", "=".repeat(count));
+    println!("{}", message);
+    println!("{}", "=".repeat(count));
+    let mut failed: usize = 0;
+    for result in results.iter().filter(|result| !result.passed()) {
+        failed += 1;
         println!(
-            "Total # of files where search text was found: {}",
-            TOTAL_FILES_TO_BE_EDITED.load(Ordering::Relaxed)
+            "Example {} of rule {}{} failed",
+            result.example + 1,
+            result.rule + 1,
+            rules_file.names[result.rule]
+                .as_ref()
+                .map(|name| format!(" ({})", name))
+                .unwrap_or_default()
         );
+        println!("  input:    {:?}", result.input);
         println!(
-            "Total # of files where search text was replaced: {}",
-            TOTAL_FILES_EDITED_OK.load(Ordering::Relaxed)
+            "  expected: {}",
+            style(format!("{:?}", result.expected)).green()
+        );
+        match &result.error {
+            Some(error) => println!("  error:    {}", style(error).red()),
+            None => println!(
+                "  actual:   {}",
+                style(format!("{:?}", result.actual)).red()
+            ),
+        }
+    }
+    let untested: usize = rules_file
+        .examples
+        .iter()
+        .filter(|examples| examples.is_empty())
+        .count();
+    println!("{}", separator);
+    println!("Total # of examples passed: {}", results.len() - failed);
+    println!("Total # of examples failed: {}", failed);
+    println!("Total # of rules without examples: {}", untested);
+    println!("{}", separator);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Adds how the run edits the files and reports to `builder`
+fn with_run_arguments(
+    mut builder: ReplaceOptionsBuilder,
+    run_arguments: &RunArguments,
+) -> ReplaceOptionsBuilder {
+    builder = builder
+        .dry_run(run_arguments.is_dry_run())
+        .preserve_mtime(run_arguments.preserve_mtime)
+        .atomic(run_arguments.atomic)
+        .preview(run_arguments.preview.is_some());
+    if run_arguments.diff || run_arguments.patch_out.is_some() {
+        builder = builder.diff(run_arguments.context);
+    }
+    if let Some(state_dir) = default_state_dir().filter(|_| !run_arguments.no_journal) {
+        builder = builder.journal_dir(state_dir);
+    }
+    if let Some(suffix) = &run_arguments.backup_suffix {
+        builder = builder.backup(Backup::Suffix(suffix.clone()));
+    } else if let Some(backup_dir) = &run_arguments.backup_dir {
+        builder = builder.backup(Backup::Directory(backup_dir.clone()));
+    }
+    builder
+}
+
+/// Confirms the run, or every match with --interactive, then runs it and prints the report
+fn run(builder: &ReplaceOptionsBuilder, run_arguments: &RunArguments, yes: bool, separator: &str) {
+    let options: ReplaceOptions = builder.clone().build();
+    let mut preview: Option<PreviewStyle> = run_arguments.preview;
+    let result: Result<Report, SarError> = if run_arguments.interactive {
+        // every match is confirmed, the prompt for the whole run is not needed
+        require_terminal(
+            "Standard input is not a terminal, cannot ask for confirmation of every match with --interactive",
+            separator,
         );
+        process_directory_interactive(&options, |candidate| {
+            confirm_match(candidate, run_arguments.context, separator)
+        })
+    } else {
+        // the preview of a run which edits the files is shown before it is confirmed
+        if let Some(style) = preview.filter(|_| !options.dry_run()) {
+            match process_directory(&builder.clone().dry_run(true).build()) {
+                Ok(report) => print_preview(&report, style, separator),
+                Err(error) => exit_with_error(&error, separator),
+            }
+            preview = None;
+        }
+        if confirm(yes, separator) {
+            process_directory(&options)
+        } else {
+            std::process::exit(0);
+        }
+    };
+    finish(result, run_arguments, preview, separator);
+}
+
+fn finish(
+    result: Result<Report, SarError>,
+    run_arguments: &RunArguments,
+    preview: Option<PreviewStyle>,
+    separator: &str,
+) {
+    match result {
+        Ok(report) => {
+            if let Some(patch_out) = &run_arguments.patch_out {
+                write_patch(&report, patch_out, separator);
+            }
+            print_report(&report, run_arguments.diff, preview, separator)
+        }
+        Err(error @ SarError::Journal { .. }) => {
+            println!("{}", separator);
+            eprintln!("{}", error);
+            eprintln!("Use --no-journal to run without an undo journal");
+            println!("{}", separator);
+            std::process::exit(1);
+        }
+        Err(error) => exit_with_error(&error, separator),
+    }
+}
+
+fn order_name(rule_order: RuleOrder) -> &'static str {
+    match rule_order {
+        RuleOrder::Sequential => "sequential",
+        RuleOrder::Simultaneous => "simultaneous",
+    }
+}
+
+fn undo_run(run_id: Option<&str>, yes: bool, separator: &str) {
+    let state_dir: PathBuf = state_dir_or_exit(separator);
+    let journal: Journal = match Journal::load(&state_dir, run_id) {
+        Ok(journal) => journal,
+        Err(error) => exit_with_error(&error, separator),
+    };
+    let message: String = format!(
+        "Undo run: {}, in directory: \"{}\", {}, restoring {} file(s)",
+        journal.run_id,
+        journal.arguments.directory.display(),
+        rules_text(&journal.arguments.rules),
+        journal.files.len()
+    );
+    let count: usize = message.chars().count();
+    println!("{}", "=".repeat(count));
+    println!("{}", message);
+    println!("{}", "=".repeat(count));
+    if !confirm(yes, separator) {
+        std::process::exit(0);
+    }
+    let report: UndoReport = match undo(&state_dir, Some(journal.run_id.as_str())) {
+        Ok(report) => report,
+        Err(error) => exit_with_error(&error, separator),
+    };
+    let mut restored: usize = 0;
+    for file in &report.files {
+        match &file.outcome {
+            UndoOutcome::Restored => {
+                restored += 1;
+                println!("Successfully restored file: {}", file.path.display());
+            }
+            UndoOutcome::Drifted => eprintln!(
+                "Warning: file changed since run {}, not restored: {}",
+                report.run_id,
+                file.path.display()
+            ),
+            UndoOutcome::Failed(error) => eprintln!("{}", error),
+        }
+    }
+    println!("{}", separator);
+    println!("Total # of files restored: {}", restored);
+    println!(
+        "Total # of files not restored: {}",
+        report.files.len() - restored
+    );
+    if restored < report.files.len() {
         println!(
-            "Total # of files not searched or edited (error): {}",
-            TOTAL_FILES_PROCESS_ERROR.load(Ordering::Relaxed)
+            "Run {} is not undone yet, to restore the other files run: sar undo {}",
+            report.run_id, report.run_id
+        );
+    }
+    println!("{}", separator);
+}
+
+fn print_history(keep: Option<usize>, separator: &str) {
+    let state_dir: PathBuf = state_dir_or_exit(separator);
+    let removed: Vec<Journal> = match keep.map(|keep| prune(&state_dir, keep)) {
+        Some(Ok(removed)) => removed,
+        Some(Err(error)) => exit_with_error(&error, separator),
+        None => Vec::new(),
+    };
+    let journals: Vec<Journal> = match history(&state_dir) {
+        Ok(journals) => journals,
+        Err(error) => exit_with_error(&error, separator),
+    };
+    println!("{}", separator);
+    for journal in &removed {
+        println!("Removed run: {}", journal.run_id);
+    }
+    if journals.is_empty() {
+        println!("No runs found in: {}", state_dir.display());
+    }
+    for journal in journals {
+        println!(
+            "{}{}  in directory: \"{}\", {}, # of files edited: {}",
+            journal.run_id,
+            if journal.undone { " (undone)" } else { "" },
+            journal.arguments.directory.display(),
+            rules_text(&journal.arguments.rules),
+            journal.files.len()
+        );
+    }
+    println!("{}", separator);
+}
+
+/// Every rule of a journaled run, as given on the command line or in the rules file
+fn rules_text(rules: &[JournalRule]) -> String {
+    let texts: Vec<String> = rules
+        .iter()
+        .map(|rule| {
+            let mut text: String = match (&rule.map_file, &rule.script) {
+                (Some(map_file), _) => {
+                    format!(
+                        "replace the keys of mapping file: \"{}\"",
+                        map_file.display()
+                    )
+                }
+                (None, Some(script)) => format!(
+                    "search for: \"{}\" and replace with script: \"{}\"",
+                    rule.search,
+                    script.display()
+                ),
+                (None, None) => format!(
+                    "search for: \"{}\" and replace with: \"{}\"",
+                    rule.search, rule.replace
+                ),
+            };
+            if !rule.flags.is_empty() {
+                text.push_str(&format!(" ({})", rule.flags.join(", ")));
+            }
+            text
+        })
+        .collect();
+    texts.join(", ")
+}
+
+fn state_dir_or_exit(separator: &str) -> PathBuf {
+    match default_state_dir() {
+        Some(state_dir) => state_dir,
+        None => {
+            println!("{}", separator);
+            eprintln!("State directory not found, set SAR_STATE_DIR");
+            println!("{}", separator);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn confirm(yes: bool, separator: &str) -> bool {
+    if yes {
+        return true;
+    }
+    require_terminal("Standard input is not a terminal, cannot ask for confirmation. Use --yes (or set SAR_YES=true) to run non-interactively", separator);
+    match Confirm::new()
+        .with_prompt("Do you want to continue?")
+        .default(true)
+        .wait_for_newline(true)
+        .interact()
+    {
+        Ok(confirmed) => confirmed,
+        Err(error) => {
+            println!("{}", separator);
+            eprintln!("Error confirming user input: {}", error);
+            println!("{}", separator);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn write_patch(report: &Report, patch_out: &Path, separator: &str) {
+    if let Err(error) = std::fs::write(patch_out, report.patch()) {
+        println!("{}", separator);
+        eprintln!(
+            "Error writing patch file: {}, error: {}",
+            patch_out.display(),
+            error
         );
+        println!("{}", separator);
+        std::process::exit(1);
+    }
+    println!("Patch saved to: {}", patch_out.display());
+}
+
+fn require_terminal(message: &str, separator: &str) {
+    if !std::io::stdin().is_terminal() {
+        println!("{}", separator);
+        eprintln!("{}", message);
+        println!("{}", separator);
+        std::process::exit(1);
+    }
+}
+
+/// Shows a match with `context` lines around it and asks what to do with it, like `git add -p`
+fn confirm_match(candidate: &MatchCandidate, context: usize, separator: &str) -> Decision {
+    println!("{}", separator);
+    let numbers: String = line_numbers(candidate.line_number, candidate.end_line_number);
+    println!(
+        "{}",
+        style(format!("{}:{}", candidate.path.display(), numbers)).bold()
+    );
+    let index: usize = candidate.line_number - 1;
+    let end: usize = candidate.end_line_number;
+    let first: usize = index.saturating_sub(context);
+    let last: usize = (end + context).min(candidate.lines.len());
+    for (number, line) in (first + 1..).zip(&candidate.lines[first..index]) {
+        println!("{}   {}", style(format!("{:>6}", number)).dim(), trim(line));
+    }
+    // the matching line(s), before and after the replacement
+    let text: String = candidate.lines[index..end].concat();
+    let text: &str = trim(&text);
+    let line_number = style(format!("{:>6}", numbers)).dim();
+    let spans: &[MatchSpan] = std::slice::from_ref(candidate.span);
+    println!(
+        "{} - {}",
+        line_number,
+        render_line(text, spans, |old, _| style(old).red().bold().to_string())
+    );
+    println!(
+        "{} + {}",
+        line_number,
+        render_line(text, spans, |_, new| style(new).green().bold().to_string())
+    );
+    for (number, line) in (end + 1..).zip(&candidate.lines[end..last]) {
+        println!("{}   {}", style(format!("{:>6}", number)).dim(), trim(line));
+    }
+    loop {
+        print!("Replace this match [y,n,a,d,q,?]? ");
+        let _ = std::io::stdout().flush();
+        let mut answer: String = String::new();
+        match std::io::stdin().read_line(&mut answer) {
+            Ok(0) => return Decision::Quit, // end of input
+            Ok(_) => (),
+            Err(error) => {
+                println!("{}", separator);
+                eprintln!("Error confirming user input: {}", error);
+                println!("{}", separator);
+                std::process::exit(1);
+            }
+        }
+        match answer.trim() {
+            "y" => return Decision::Yes,
+            "n" => return Decision::No,
+            "a" => return Decision::AllInFile,
+            "d" => return Decision::SkipFile,
+            "q" => return Decision::Quit,
+            _ => println!(
+                "y - replace this match\nn - do not replace this match\na - replace this and all later matches in the file\nd - do not replace this or any later match in the file\nq - quit, do not replace this or any remaining match"
+            ),
+        }
+    }
+}
+
+fn print_report(report: &Report, diff: bool, preview: Option<PreviewStyle>, separator: &str) {
+    for file in &report.files {
+        match &file.outcome {
+            FileOutcome::Found => {
+                println!("Search text found in file: {}", file.path.display());
+                print_rule_matches(&file.rule_matches, "  # of matches of rule");
+                print_details(file, diff, preview);
+            }
+            FileOutcome::Edited => {
+                println!("Search text found in file: {}", file.path.display());
+                print_rule_matches(&file.rule_matches, "  # of matches of rule");
+                print_details(file, diff, preview);
+                println!("Successfully edited file: {}", file.path.display());
+                if let Some(backup) = &file.backup {
+                    println!("Backup of file saved to: {}", backup.display());
+                }
+            }
+            FileOutcome::Failed(error) => eprintln!("{}", error),
+            FileOutcome::RolledBack => {
+                println!("Search text found in file: {}", file.path.display());
+                println!("Restored file after aborted run: {}", file.path.display());
+            }
+        }
+    }
+    for error in &report.dir_errors {
+        eprintln!("{}", error);
+    }
+    println!("{}", separator);
+    println!(
+        "Total # of files where search text was found: {}",
+        report.files_found()
+    );
+    println!(
+        "Total # of files where search text was replaced: {}",
+        report.files_edited()
+    );
+    println!(
+        "Total # of files not searched or edited (error): {}",
+        report.files_failed()
+    );
+    println!(
+        "Total # of directories or files not entered (error): {}",
+        report.dir_errors.len()
+    );
+    print_rule_matches(&report.rule_matches(), "Total # of matches of rule");
+    if !report.unmatched_keys.is_empty() {
         println!(
-            "Total # of directories or files not entered (error): {}",
-            TOTAL_DIR_READ_ERROR.load(Ordering::Relaxed)
+            "Total # of mapping keys never found: {}",
+            report.unmatched_keys.len()
         );
+        for (_, key) in &report.unmatched_keys {
+            println!("  {}", key);
+        }
+    }
+    if let Some(run_id) = &report.run_id {
+        println!("Run id: {} (to undo, run: sar undo {})", run_id, run_id);
+    }
+    println!("{}", separator);
+    if report.aborted {
+        eprintln!("Atomic run aborted because of the error(s) above, files were not edited");
         println!("{}", separator);
+        std::process::exit(1);
+    }
+}
+
+/// Number of matches of every rule, only when there are several rules
+fn print_rule_matches(rule_matches: &[usize], label: &str) {
+    if rule_matches.len() > 1 {
+        for (index, matches) in rule_matches.iter().enumerate() {
+            println!("{} {}: {}", label, index + 1, matches);
+        }
+    }
+}
+
+/// Lines the run is about to replace, printed before it is confirmed
+fn print_preview(report: &Report, preview: PreviewStyle, separator: &str) {
+    for file in &report.files {
+        if matches!(file.outcome, FileOutcome::Found) {
+            println!("Search text found in file: {}", file.path.display());
+            print_details(file, false, Some(preview));
+        }
+    }
+    println!("{}", separator);
+}
+
+fn print_details(file: &FileReport, diff: bool, preview: Option<PreviewStyle>) {
+    if let Some(preview) = preview {
+        for line_match in &file.lines {
+            let text: &str = trim(&line_match.line);
+            let mut numbers: String =
+                line_numbers(line_match.line_number, line_match.end_line_number);
+            // with several rules, the line is the one the rule saw
+            if file.rule_matches.len() > 1 {
+                let mut rules: Vec<String> = Vec::new();
+                for span in &line_match.spans {
+                    let rule: String = (span.rule + 1).to_string();
+                    if !rules.contains(&rule) {
+                        rules.push(rule);
+                    }
+                }
+                numbers = format!("{} (rule {})", numbers, rules.join(","));
+            }
+            let number = style(format!("{:>6}", numbers)).dim();
+            match preview {
+                PreviewStyle::Inline => println!(
+                    "{}: {}",
+                    number,
+                    render_line(text, &line_match.spans, |old, new| {
+                        if console::colors_enabled() {
+                            // console has no strikethrough style, use the escape code directly
+                            format!(
+                                "{}{}",
+                                style(format!("\x1b[9m{}\x1b[29m", old)).red(),
+                                style(new).green().bold()
+                            )
+                        } else {
+                            format!("[-{}-]{{+{}+}}", old, new)
+                        }
+                    })
+                ),
+                PreviewStyle::BeforeAfter => {
+                    println!(
+                        "{} - {}",
+                        number,
+                        render_line(text, &line_match.spans, |old, _| {
+                            style(old).red().bold().to_string()
+                        })
+                    );
+                    println!(
+                        "{} + {}",
+                        number,
+                        render_line(text, &line_match.spans, |_, new| {
+                            style(new).green().bold().to_string()
+                        })
+                    );
+                }
+            }
+        }
+    }
+    if let (true, Some(file_diff)) = (diff, &file.diff) {
+        print!("{}", file_diff);
+    }
+}
+
+/// "12", or "12-14" for a match spanning several lines
+fn line_numbers(line_number: usize, end_line_number: usize) -> String {
+    if line_number == end_line_number {
+        line_number.to_string()
     } else {
-        std::process::exit(0);
+        format!("{}-{}", line_number, end_line_number)
+    }
+}
+
+fn trim(line: &str) -> &str {
+    line.trim_end_matches(&['\r', '\n'][..])
+}
+
+/// Renders `text` with every match replaced by `render_match(matched text, replacement)`
+fn render_line<F: Fn(&str, &str) -> String>(
+    text: &str,
+    spans: &[MatchSpan],
+    render_match: F,
+) -> String {
+    let mut rendered: String = String::new();
+    let mut end: usize = 0;
+    for span in spans {
+        // spans reaching into the line ending are cut at the end of the text
+        let start: usize = span.start.min(text.len()).max(end);
+        let span_end: usize = span.end.min(text.len()).max(start);
+        rendered.push_str(&text[end..start]);
+        rendered.push_str(&render_match(&text[start..span_end], &span.replacement));
+        end = span_end;
+    }
+    rendered.push_str(&text[end..]);
+    rendered
+}
+
+/// Always, never, or only on a terminal when NO_COLOR is not set (https://no-color.org)
+fn set_colors(color: ColorChoice) {
+    let no_color: bool = std::env::var_os("NO_COLOR")
+        .map(|value| !value.is_empty())
+        .unwrap_or(false);
+    let (stdout, stderr): (bool, bool) = match color {
+        ColorChoice::Always => (true, true),
+        ColorChoice::Never => (false, false),
+        ColorChoice::Auto => (
+            !no_color && std::io::stdout().is_terminal(),
+            !no_color && std::io::stderr().is_terminal(),
+        ),
+    };
+    console::set_colors_enabled(stdout);
+    console::set_colors_enabled_stderr(stderr);
+}
+
+fn exit_with_error(error: &SarError, separator: &str) -> ! {
+    println!("{}", separator);
+    match error {
+        SarError::InvalidPattern {
+            pattern,
+            message,
+            span: Some(span),
+            ..
+        } => {
+            // highlight the offending part of the pattern and point at it
+            let end: usize = span.end.max(span.start + 1).min(pattern.len());
+            let end: usize = (end..=pattern.len())
+                .find(|i| pattern.is_char_boundary(*i))
+                .unwrap_or(pattern.len());
+            let start: usize = span.start.min(end);
+            eprintln!(
+                "Invalid search pattern at line {}, column {}:",
+                span.line, span.column
+            );
+            eprintln!(
+                "    {}{}{}",
+                &pattern[..start],
+                style(&pattern[start..end]).for_stderr().red().bold(),
+                &pattern[end..]
+            );
+            eprintln!(
+                "    {}{}",
+                " ".repeat(pattern[..start].chars().count()),
+                style("^".repeat(pattern[start..end].chars().count().max(1)))
+                    .for_stderr()
+                    .red()
+                    .bold()
+            );
+            eprintln!("error: {}", message);
+        }
+        _ => eprintln!("{}", error),
     }
+    println!("{}", separator);
+    std::process::exit(1);
 }
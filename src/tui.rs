@@ -0,0 +1,420 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use sar::core::{
+    review, Hunk, MatchSpan, ReplaceOptions, ReplaceOptionsBuilder, Review, ReviewFile,
+};
+use std::io;
+
+const HELP: &str =
+    "Tab: next pane | Up/Down: move | Space: toggle | Enter: open file | Ctrl+A: apply | Esc: quit";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Files,
+    Hunks,
+    Search,
+    Replace,
+}
+
+struct App {
+    builder: ReplaceOptionsBuilder,
+    search: String,
+    replace: String,
+    context: usize,
+    review: Review,
+    /// Error of the latest search, the previous review is kept
+    error: Option<String>,
+    /// Search or replace text changed since the latest search, which runs again on Enter or when
+    /// the text loses focus
+    edited: bool,
+    focus: Focus,
+    files: ListState,
+    hunk: usize,
+}
+
+/// Lets the user review the replacements in a full screen terminal UI. Returns the options (with
+/// the edited search and replace text) and the selected hunks if the user chose to apply them,
+/// `None` if the user quit
+pub fn run(
+    builder: ReplaceOptionsBuilder,
+    search: String,
+    replace: String,
+    context: usize,
+) -> io::Result<Option<(ReplaceOptions, Review)>> {
+    let mut app: App = App {
+        builder,
+        search,
+        replace,
+        context,
+        review: Review::default(),
+        error: None,
+        edited: false,
+        focus: Focus::Files,
+        files: ListState::default(),
+        hunk: 0,
+    };
+    app.refresh();
+    let mut terminal: DefaultTerminal = ratatui::init();
+    let result: io::Result<bool> = app.event_loop(&mut terminal);
+    ratatui::restore();
+    match result? {
+        true => Ok(Some((app.options(), app.review))),
+        false => Ok(None),
+    }
+}
+
+impl App {
+    fn options(&self) -> ReplaceOptions {
+        self.builder
+            .clone()
+            .search(self.search.as_str())
+            .replace(self.replace.as_str())
+            .build()
+    }
+
+    /// Searches again with the current search and replace text. The selected file stays selected
+    /// if it still has hunks
+    fn refresh(&mut self) {
+        self.edited = false;
+        if self.search.is_empty() {
+            self.error = Some(String::from("Search text cannot be blank"));
+            return;
+        }
+        match review(&self.options()) {
+            Ok(review) => {
+                let selected: Option<&ReviewFile> = self
+                    .files
+                    .selected()
+                    .and_then(|file| self.review.files.get(file));
+                let file: Option<usize> = selected
+                    .and_then(|selected| {
+                        review
+                            .files
+                            .iter()
+                            .position(|file| file.path == selected.path)
+                    })
+                    .or(if review.files.is_empty() {
+                        None
+                    } else {
+                        Some(0)
+                    });
+                if file != self.files.selected() {
+                    self.hunk = 0;
+                }
+                self.review = review;
+                self.error = None;
+                self.files.select(file);
+                if let Some(file) = file {
+                    self.hunk = self
+                        .hunk
+                        .min(self.review.files[file].hunks.len().saturating_sub(1));
+                }
+            }
+            Err(error) => self.error = Some(error.to_string()),
+        }
+    }
+
+    /// Returns true if the user chose to apply the selected hunks
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<bool> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    match key.code {
+                        // apply what the user saw, a changed text is searched first
+                        KeyCode::Char('a') if self.edited => self.refresh(),
+                        KeyCode::Char('a') if self.error.is_none() => return Ok(true),
+                        KeyCode::Char('c') => return Ok(false),
+                        _ => (),
+                    }
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Esc => return Ok(false),
+                    KeyCode::Tab => self.next_focus(),
+                    _ => match self.focus {
+                        Focus::Search | Focus::Replace => self.edit(key),
+                        Focus::Files | Focus::Hunks => {
+                            if key.code == KeyCode::Char('q') {
+                                return Ok(false);
+                            }
+                            self.navigate(key)
+                        }
+                    },
+                }
+            }
+        }
+    }
+
+    fn next_focus(&mut self) {
+        if self.edited {
+            self.refresh();
+        }
+        self.focus = match self.focus {
+            Focus::Files => Focus::Hunks,
+            Focus::Hunks => Focus::Search,
+            Focus::Search => Focus::Replace,
+            Focus::Replace => Focus::Files,
+        };
+    }
+
+    fn edit(&mut self, key: KeyEvent) {
+        let text: &mut String = match self.focus {
+            Focus::Search => &mut self.search,
+            _ => &mut self.replace,
+        };
+        match key.code {
+            KeyCode::Char(c) => text.push(c),
+            KeyCode::Backspace => {
+                text.pop();
+            }
+            KeyCode::Enter => {
+                if self.edited {
+                    self.refresh();
+                }
+                self.focus = Focus::Files;
+                return;
+            }
+            _ => return,
+        }
+        // searching runs on Enter or Tab, not on every key
+        self.edited = true;
+    }
+
+    fn navigate(&mut self, key: KeyEvent) {
+        let file: usize = match self.files.selected() {
+            Some(file) => file,
+            None => return,
+        };
+        let files: usize = self.review.files.len();
+        let hunks: usize = self.review.files[file].hunks.len();
+        match (self.focus, key.code) {
+            (Focus::Files, KeyCode::Up) => self.select_file(file.saturating_sub(1)),
+            (Focus::Files, KeyCode::Down) => self.select_file((file + 1).min(files - 1)),
+            (Focus::Files, KeyCode::Char(' ')) => {
+                let selected: bool = self.review.files[file].selected() == 0;
+                self.review.select_file(file, selected);
+            }
+            (Focus::Files, KeyCode::Enter | KeyCode::Right) => self.focus = Focus::Hunks,
+            (Focus::Hunks, KeyCode::Up) => self.hunk = self.hunk.saturating_sub(1),
+            (Focus::Hunks, KeyCode::Down) => self.hunk = (self.hunk + 1).min(hunks - 1),
+            (Focus::Hunks, KeyCode::Char(' ')) => self.review.toggle(file, self.hunk),
+            (Focus::Hunks, KeyCode::Left) => self.focus = Focus::Files,
+            _ => (),
+        }
+    }
+
+    fn select_file(&mut self, file: usize) {
+        self.files.select(Some(file));
+        self.hunk = 0;
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search, replace, main, status] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [files, diff] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main);
+        frame.render_widget(
+            Paragraph::new(self.search.as_str()).block(self.block("Search", Focus::Search)),
+            search,
+        );
+        frame.render_widget(
+            Paragraph::new(self.replace.as_str()).block(self.block("Replace", Focus::Replace)),
+            replace,
+        );
+        let items: Vec<ListItem> = self
+            .review
+            .files
+            .iter()
+            .map(|file| {
+                ListItem::new(format!(
+                    "{} {} ({}/{})",
+                    checkbox(file.selected() > 0),
+                    file.path.display(),
+                    file.selected(),
+                    file.hunks.len()
+                ))
+            })
+            .collect();
+        let title: String = format!(
+            "Files ({} of {} hunks selected)",
+            self.review.selected(),
+            self.review
+                .files
+                .iter()
+                .map(|file| file.hunks.len())
+                .sum::<usize>()
+        );
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(self.block(&title, Focus::Files))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+            files,
+            &mut self.files,
+        );
+        self.draw_diff(frame, diff);
+        let status_text: Line = match &self.error {
+            Some(error) => Line::styled(
+                error.lines().last().unwrap_or_default().to_string(),
+                Color::Red,
+            ),
+            None if self.edited => {
+                Line::styled(format!("Enter: search again | {}", HELP), Color::Yellow)
+            }
+            None if !self.review.errors.is_empty() => Line::styled(
+                format!(
+                    "{} file(s) or directory(s) could not be searched | {}",
+                    self.review.errors.len(),
+                    HELP
+                ),
+                Color::Yellow,
+            ),
+            None => Line::from(HELP),
+        };
+        frame.render_widget(Paragraph::new(status_text), status);
+    }
+
+    /// Side by side diff of the selected file, original lines on the left, replaced on the right
+    fn draw_diff(&self, frame: &mut Frame, area: Rect) {
+        let block: Block = self.block("Hunks", Focus::Hunks);
+        let inner: Rect = block.inner(area);
+        frame.render_widget(block, area);
+        let file: &ReviewFile = match self.files.selected() {
+            Some(file) => &self.review.files[file],
+            None => return,
+        };
+        let mut left: Vec<Line> = Vec::new();
+        let mut right: Vec<Line> = Vec::new();
+        let mut current_row: usize = 0;
+        let mut shown: usize = 0; // lines before this index are already shown
+        let mut next: usize = 0; // hunks before this index are already shown
+        for (index, hunk) in file.hunks.iter().enumerate() {
+            if index < next {
+                continue;
+            }
+            let first: usize = (hunk.line_number - 1)
+                .saturating_sub(self.context)
+                .max(shown);
+            let last: usize = (hunk.end_line_number + self.context).min(file.lines.len());
+            if first > shown && shown > 0 {
+                left.push(Line::styled("...", Color::DarkGray));
+                right.push(Line::styled("...", Color::DarkGray));
+            }
+            let mut line_index: usize = first;
+            while line_index < last {
+                // every hunk starting on the line gets a row, e.g. the hunks of several rules or a
+                // hunk on the last line of a hunk spanning several lines
+                let starting: usize = file.hunks[next..]
+                    .iter()
+                    .take_while(|hunk| hunk.line_number <= line_index + 1)
+                    .count();
+                if starting == 0 {
+                    let number: String = format!("{:>5} ", line_index + 1);
+                    let text: String = format!("    {}{}", number, trim(&file.lines[line_index]));
+                    left.push(Line::raw(text.clone()));
+                    right.push(Line::raw(text));
+                    line_index += 1;
+                    continue;
+                }
+                for index in next..next + starting {
+                    let hunk: &Hunk = &file.hunks[index];
+                    let current: bool = index == self.hunk;
+                    if current {
+                        current_row = left.len();
+                    }
+                    let marker: Span = Span::styled(
+                        format!("{} ", checkbox(hunk.selected)),
+                        if current && self.focus == Focus::Hunks {
+                            Style::default().add_modifier(Modifier::REVERSED)
+                        } else {
+                            Style::default()
+                        },
+                    );
+                    let number: String = format!("{:>5} ", hunk.line_number);
+                    let original: &str = trim(&hunk.line);
+                    let mut old: Vec<Span> = vec![marker.clone(), Span::raw(number.clone())];
+                    old.extend(highlight(original, &hunk.spans, false, Color::Red));
+                    let mut new: Vec<Span> = vec![marker, Span::raw(number)];
+                    if hunk.selected {
+                        new.extend(highlight(original, &hunk.spans, true, Color::Green));
+                    } else {
+                        new.push(Span::styled(flatten(original), Color::DarkGray));
+                    }
+                    left.push(Line::from(old));
+                    right.push(Line::from(new));
+                    // a hunk spanning several lines is shown in a single row
+                    line_index = line_index.max(hunk.end_line_number);
+                }
+                next += starting;
+            }
+            shown = line_index.max(shown);
+        }
+        // keep the current hunk in view
+        let scroll: u16 = current_row.saturating_sub(inner.height as usize / 3) as u16;
+        let [left_area, right_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(inner);
+        frame.render_widget(Paragraph::new(left).scroll((scroll, 0)), left_area);
+        frame.render_widget(Paragraph::new(right).scroll((scroll, 0)), right_area);
+    }
+
+    fn block<'a>(&self, title: &'a str, focus: Focus) -> Block<'a> {
+        let block: Block = Block::bordered().title(title);
+        if self.focus == focus {
+            block.border_style(Style::default().fg(Color::Cyan))
+        } else {
+            block
+        }
+    }
+}
+
+fn checkbox(selected: bool) -> &'static str {
+    if selected {
+        "[x]"
+    } else {
+        "[ ]"
+    }
+}
+
+fn trim(line: &str) -> &str {
+    line.trim_end_matches(&['\r', '\n'][..])
+}
+
+/// `text` with every match highlighted, either the matched text or its replacement
+fn highlight(text: &str, spans: &[MatchSpan], replaced: bool, color: Color) -> Vec<Span<'static>> {
+    let style: Style = Style::default().fg(color).add_modifier(Modifier::BOLD);
+    let mut rendered: Vec<Span> = Vec::new();
+    let mut last: usize = 0;
+    for span in spans {
+        // spans reaching into the line ending are cut at the end of the text
+        let start: usize = span.start.min(text.len()).max(last);
+        let end: usize = span.end.min(text.len()).max(start);
+        rendered.push(Span::raw(flatten(&text[last..start])));
+        if replaced {
+            rendered.push(Span::styled(flatten(trim(&span.replacement)), style));
+        } else {
+            rendered.push(Span::styled(flatten(&text[start..end]), style));
+        }
+        last = end;
+    }
+    rendered.push(Span::raw(flatten(&text[last..])));
+    rendered
+}
+
+/// Line endings inside a multiline match shown as a symbol, to keep a hunk in a single row
+fn flatten(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\n', "\u{23ce}")
+}
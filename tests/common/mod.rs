@@ -6,34 +6,29 @@ use std::{
     slice::Iter,
 };
 
-pub fn setup() -> Result<PathBuf> {
-    tear_down()?;
+/// Creates `./tests/test_run/<test_name>` and returns its path.
+/// Every test gets its own run directory, so integration tests can run in parallel.
+pub fn setup(test_name: &str) -> Result<PathBuf> {
+    tear_down(test_name)?;
     let mut current_dir: PathBuf = env::current_dir()?;
     println!("setup current directory: {}", current_dir.display());
     current_dir.push("tests");
     if Path::new(current_dir.as_os_str()).try_exists()? {
+        let run_directory: PathBuf = run_directory(test_name)?;
         create_directories(
             [
-                current_dir.display().to_string().as_str(),
-                "test_run",
+                run_directory.display().to_string().as_str(),
                 "dir1",
                 "dir11",
             ]
             .iter(),
         )?;
-        create_directories(
-            [
-                current_dir.display().to_string().as_str(),
-                "test_run",
-                "dir2",
-            ]
-            .iter(),
-        )?;
-        copy_files("original-1.txt", "actual-1.txt")?;
-        copy_files("original-2.txt", "actual-2.txt")?;
-        copy_files("original-2.json", "actual-2.json")?;
-        copy_files("original-3.js", "actual-3.js")?;
-        Ok(current_dir)
+        create_directories([run_directory.display().to_string().as_str(), "dir2"].iter())?;
+        copy_files("original-1.txt", "actual-1.txt", run_directory.as_path())?;
+        copy_files("original-2.txt", "actual-2.txt", run_directory.as_path())?;
+        copy_files("original-2.json", "actual-2.json", run_directory.as_path())?;
+        copy_files("original-3.js", "actual-3.js", run_directory.as_path())?;
+        Ok(run_directory)
     } else {
         let mut message = "tests directory not found in current directory: ".to_string();
         message.push_str(current_dir.display().to_string().as_str());
@@ -41,39 +36,37 @@ pub fn setup() -> Result<PathBuf> {
     }
 }
 
-pub fn tear_down() -> Result<()> {
-    let mut current_dir: PathBuf = env::current_dir()?;
-    println!("tear_down current directory: {}", current_dir.display());
-    current_dir.push("tests");
-    current_dir.push("test_run"); // ./tests/test_run
-    if Path::new(current_dir.as_os_str()).try_exists()? {
-        println!("{} exists", current_dir.display());
-        remove_dir_all(current_dir.as_path())?;
+pub fn tear_down(test_name: &str) -> Result<()> {
+    let run_directory: PathBuf = run_directory(test_name)?; // ./tests/test_run/<test_name>
+    if Path::new(run_directory.as_os_str()).try_exists()? {
+        println!("{} exists", run_directory.display());
+        remove_dir_all(run_directory.as_path())?;
     } else {
-        println!("{} doesn't exist", current_dir.display());
+        println!("{} doesn't exist", run_directory.display());
     }
     Ok(())
 }
 
-pub fn assert_results(expected_file_name: &str, actual_file_name: &str, tests_directory: &Path) {
+pub fn assert_results(expected_file_name: &str, actual_file_name: &str, run_directory: &Path) {
     let source_file_path: PathBuf = [
-        tests_directory.display().to_string().as_str(),
-        "resources",
+        resources_directory()
+            .expect("unable to find resources directory")
+            .display()
+            .to_string()
+            .as_str(),
         expected_file_name,
     ]
     .iter()
     .collect();
     let target_file_path: PathBuf = [
-        tests_directory.display().to_string().as_str(),
-        "test_run",
+        run_directory.display().to_string().as_str(),
         actual_file_name,
     ]
     .iter()
     .collect();
     compare_file_contents(source_file_path.as_path(), target_file_path.as_path());
     let target_file_path: PathBuf = [
-        tests_directory.display().to_string().as_str(),
-        "test_run",
+        run_directory.display().to_string().as_str(),
         "dir2",
         actual_file_name,
     ]
@@ -81,8 +74,7 @@ pub fn assert_results(expected_file_name: &str, actual_file_name: &str, tests_di
     .collect();
     compare_file_contents(source_file_path.as_path(), target_file_path.as_path());
     let target_file_path: PathBuf = [
-        tests_directory.display().to_string().as_str(),
-        "test_run",
+        run_directory.display().to_string().as_str(),
         "dir1",
         "dir11",
         actual_file_name,
@@ -101,6 +93,29 @@ fn compare_file_contents(source_file_path: &Path, target_file_path: &Path) {
     assert_eq!(source_file_contents, target_file_contents);
 }
 
+fn run_directory(test_name: &str) -> Result<PathBuf> {
+    let current_dir: PathBuf = env::current_dir()?;
+    Ok([
+        current_dir.display().to_string().as_str(),
+        "tests",
+        "test_run",
+        test_name,
+    ]
+    .iter()
+    .collect())
+}
+
+fn resources_directory() -> Result<PathBuf> {
+    let current_dir: PathBuf = env::current_dir()?;
+    Ok([
+        current_dir.display().to_string().as_str(),
+        "tests",
+        "resources",
+    ]
+    .iter()
+    .collect())
+}
+
 fn create_directories(paths: Iter<&str>) -> Result<()> {
     let target_directory: PathBuf = paths.collect();
     println!("{}", target_directory.display());
@@ -108,20 +123,15 @@ fn create_directories(paths: Iter<&str>) -> Result<()> {
     Ok(())
 }
 
-fn copy_files(source_file_name: &str, target_file_name: &str) -> Result<()> {
-    let current_dir: PathBuf = env::current_dir()?;
+fn copy_files(source_file_name: &str, target_file_name: &str, run_directory: &Path) -> Result<()> {
     let text_file_from: PathBuf = [
-        current_dir.display().to_string().as_str(),
-        "tests",
-        "resources",
+        resources_directory()?.display().to_string().as_str(),
         source_file_name,
     ]
     .iter()
     .collect();
     let text_file_to: PathBuf = [
-        current_dir.display().to_string().as_str(),
-        "tests",
-        "test_run",
+        run_directory.display().to_string().as_str(),
         "dir1",
         "dir11",
         target_file_name,
@@ -130,9 +140,7 @@ fn copy_files(source_file_name: &str, target_file_name: &str) -> Result<()> {
     .collect();
     copy(&text_file_from, &text_file_to)?;
     let text_file_to: PathBuf = [
-        current_dir.display().to_string().as_str(),
-        "tests",
-        "test_run",
+        run_directory.display().to_string().as_str(),
         "dir2",
         target_file_name,
     ]
@@ -140,9 +148,7 @@ fn copy_files(source_file_name: &str, target_file_name: &str) -> Result<()> {
     .collect();
     copy(&text_file_from, &text_file_to)?;
     let text_file_to: PathBuf = [
-        current_dir.display().to_string().as_str(),
-        "tests",
-        "test_run",
+        run_directory.display().to_string().as_str(),
         target_file_name,
     ]
     .iter()
@@ -1,46 +1,47 @@
-use std::{path::PathBuf, sync::atomic::Ordering};
-
 use crate::common::{assert_results, setup, tear_down};
-use sar::core::{process_directory, TOTAL_FILES_EDITED_OK, TOTAL_FILES_TO_BE_EDITED};
+use sar::core::{
+    apply, build_matchers, history, load_mapping, load_rules, process_directory,
+    process_directory_interactive, review, run_examples, undo, Backup, CounterScope, Decision,
+    ExampleResult, FileOutcome, FileReport, Matcher, ReplaceOptions, ReplaceOptionsBuilder, Report,
+    Review, Rule, RuleOrder, RulesFile, SarError, UndoOutcome, UndoReport,
+};
+use std::path::PathBuf;
 
 mod common;
 
 #[test]
 fn it_searches_and_replaces_text_from_english_to_english_no_regex() {
-    match setup() {
-        Ok(tests_directory) => {
-            let test_run_directory: PathBuf =
-                [tests_directory.display().to_string().as_str(), "test_run"]
-                    .iter()
-                    .collect();
-            let file_extensions: Vec<String> = vec!["json".to_string(), "js".to_string()];
-            let ignored_dirs: Vec<String> = Vec::new();
-            let search: String = "positive".to_string();
-            let replace: String = "negative".to_string();
-            let dry_run: bool = false;
-            process_directory(
-                test_run_directory.as_path(),
-                &file_extensions,
-                &ignored_dirs,
-                search.as_str(),
-                replace.as_str(),
-                &dry_run,
-            );
-            assert_eq!(TOTAL_FILES_TO_BE_EDITED.load(Ordering::Relaxed), 6);
-            assert_eq!(TOTAL_FILES_EDITED_OK.load(Ordering::Relaxed), 6);
+    match setup("searches_and_replaces_text_from_english_to_english_no_regex") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["json", "js"])
+                .search("positive")
+                .replace("negative")
+                .dry_run(false)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_found(), 6);
+            assert_eq!(report.files_edited(), 6);
             assert_results(
                 "expected-21.json",
                 "actual-2.json",
-                tests_directory.as_path(),
+                test_run_directory.as_path(),
+            );
+            assert_results(
+                "expected-31.js",
+                "actual-3.js",
+                test_run_directory.as_path(),
+            );
+            assert_results(
+                "original-1.txt",
+                "actual-1.txt",
+                test_run_directory.as_path(),
             );
-            assert_results("expected-31.js", "actual-3.js", tests_directory.as_path());
-            assert_results("original-1.txt", "actual-1.txt", tests_directory.as_path());
         }
         Err(error) => eprintln!("{}", error),
     }
-    TOTAL_FILES_TO_BE_EDITED.store(0, Ordering::Relaxed);
-    TOTAL_FILES_EDITED_OK.store(0, Ordering::Relaxed);
-    match tear_down() {
+    match tear_down("searches_and_replaces_text_from_english_to_english_no_regex") {
         Ok(()) => println!("tear_down ok"),
         Err(error) => eprintln!("tear_down error: {}", error),
     }
@@ -48,41 +49,38 @@ fn it_searches_and_replaces_text_from_english_to_english_no_regex() {
 
 #[test]
 fn it_searches_and_replaces_text_from_english_to_english_regex() {
-    match setup() {
-        Ok(tests_directory) => {
-            let test_run_directory: PathBuf =
-                [tests_directory.display().to_string().as_str(), "test_run"]
-                    .iter()
-                    .collect();
-            let file_extensions: Vec<String> = vec!["txt".to_string()];
-            let ignored_dirs: Vec<String> = Vec::new();
-            let search: String = r"(?P<a>[a-z]{3})\.(?P<b>[a-z]{3})\.(?P<c>\d{3})".to_string();
-            let replace: String = r"$a.$b.com".to_string();
-            let dry_run: bool = false;
-            process_directory(
+    match setup("searches_and_replaces_text_from_english_to_english_regex") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["txt"])
+                .search(r"(?P<a>[a-z]{3})\.(?P<b>[a-z]{3})\.(?P<c>\d{3})")
+                .replace(r"$a.$b.com")
+                .dry_run(false)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_found(), 3);
+            assert_eq!(report.files_edited(), 3);
+            assert_results(
+                "original-1.txt",
+                "actual-1.txt",
                 test_run_directory.as_path(),
-                &file_extensions,
-                &ignored_dirs,
-                search.as_str(),
-                replace.as_str(),
-                &dry_run,
-            );
-            assert_eq!(TOTAL_FILES_TO_BE_EDITED.load(Ordering::Relaxed), 3);
-            assert_eq!(TOTAL_FILES_EDITED_OK.load(Ordering::Relaxed), 3);
-            assert_results("original-1.txt", "actual-1.txt", tests_directory.as_path());
-            assert_results("expected-2.txt", "actual-2.txt", tests_directory.as_path());
+            );
+            assert_results(
+                "expected-2.txt",
+                "actual-2.txt",
+                test_run_directory.as_path(),
+            );
             assert_results(
                 "original-2.json",
                 "actual-2.json",
-                tests_directory.as_path(),
+                test_run_directory.as_path(),
             );
-            assert_results("original-3.js", "actual-3.js", tests_directory.as_path());
+            assert_results("original-3.js", "actual-3.js", test_run_directory.as_path());
         }
         Err(error) => eprintln!("{}", error),
     }
-    TOTAL_FILES_TO_BE_EDITED.store(0, Ordering::Relaxed);
-    TOTAL_FILES_EDITED_OK.store(0, Ordering::Relaxed);
-    match tear_down() {
+    match tear_down("searches_and_replaces_text_from_english_to_english_regex") {
         Ok(()) => println!("tear_down ok"),
         Err(error) => eprintln!("tear_down error: {}", error),
     }
@@ -90,40 +88,37 @@ fn it_searches_and_replaces_text_from_english_to_english_regex() {
 
 #[test]
 fn it_searches_and_replaces_text_from_spanish_to_malayalam() {
-    match setup() {
-        Ok(tests_directory) => {
-            let test_run_directory: PathBuf =
-                [tests_directory.display().to_string().as_str(), "test_run"]
-                    .iter()
-                    .collect();
-            let file_extensions: Vec<String> = vec!["json".to_string(), "js".to_string()];
-            let ignored_dirs: Vec<String> = Vec::new();
-            let search: String = "número".to_string();
-            let replace: String = "നമ്പർ".to_string();
-            let dry_run: bool = false;
-            process_directory(
-                test_run_directory.as_path(),
-                &file_extensions,
-                &ignored_dirs,
-                search.as_str(),
-                replace.as_str(),
-                &dry_run,
-            );
-            assert_eq!(TOTAL_FILES_TO_BE_EDITED.load(Ordering::Relaxed), 6);
-            assert_eq!(TOTAL_FILES_EDITED_OK.load(Ordering::Relaxed), 6);
+    match setup("searches_and_replaces_text_from_spanish_to_malayalam") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["json", "js"])
+                .search("número")
+                .replace("നമ്പർ")
+                .dry_run(false)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_found(), 6);
+            assert_eq!(report.files_edited(), 6);
             assert_results(
                 "expected-22.json",
                 "actual-2.json",
-                tests_directory.as_path(),
+                test_run_directory.as_path(),
+            );
+            assert_results(
+                "expected-32.js",
+                "actual-3.js",
+                test_run_directory.as_path(),
+            );
+            assert_results(
+                "original-1.txt",
+                "actual-1.txt",
+                test_run_directory.as_path(),
             );
-            assert_results("expected-32.js", "actual-3.js", tests_directory.as_path());
-            assert_results("original-1.txt", "actual-1.txt", tests_directory.as_path());
         }
         Err(error) => eprintln!("{}", error),
     }
-    TOTAL_FILES_TO_BE_EDITED.store(0, Ordering::Relaxed);
-    TOTAL_FILES_EDITED_OK.store(0, Ordering::Relaxed);
-    match tear_down() {
+    match tear_down("searches_and_replaces_text_from_spanish_to_malayalam") {
         Ok(()) => println!("tear_down ok"),
         Err(error) => eprintln!("tear_down error: {}", error),
     }
@@ -131,40 +126,33 @@ fn it_searches_and_replaces_text_from_spanish_to_malayalam() {
 
 #[test]
 fn it_searches_but_wont_replace_text_in_dry_run_mode() {
-    match setup() {
-        Ok(tests_directory) => {
-            let test_run_directory: PathBuf =
-                [tests_directory.display().to_string().as_str(), "test_run"]
-                    .iter()
-                    .collect();
-            let file_extensions: Vec<String> = vec!["json".to_string(), "js".to_string()];
-            let ignored_dirs: Vec<String> = Vec::new();
-            let search: String = "positive".to_string();
-            let replace: String = "negative".to_string();
-            let dry_run: bool = true;
-            process_directory(
-                test_run_directory.as_path(),
-                &file_extensions,
-                &ignored_dirs,
-                search.as_str(),
-                replace.as_str(),
-                &dry_run,
-            );
-            assert_eq!(TOTAL_FILES_TO_BE_EDITED.load(Ordering::Relaxed), 6);
-            assert_eq!(TOTAL_FILES_EDITED_OK.load(Ordering::Relaxed), 0);
+    match setup("searches_but_wont_replace_text_in_dry_run_mode") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["json", "js"])
+                .search("positive")
+                .replace("negative")
+                .dry_run(true)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_found(), 6);
+            assert_eq!(report.files_edited(), 0);
             assert_results(
                 "original-2.json",
                 "actual-2.json",
-                tests_directory.as_path(),
+                test_run_directory.as_path(),
+            );
+            assert_results("original-3.js", "actual-3.js", test_run_directory.as_path());
+            assert_results(
+                "original-1.txt",
+                "actual-1.txt",
+                test_run_directory.as_path(),
             );
-            assert_results("original-3.js", "actual-3.js", tests_directory.as_path());
-            assert_results("original-1.txt", "actual-1.txt", tests_directory.as_path());
         }
         Err(error) => eprintln!("{}", error),
     }
-    TOTAL_FILES_TO_BE_EDITED.store(0, Ordering::Relaxed);
-    TOTAL_FILES_EDITED_OK.store(0, Ordering::Relaxed);
-    match tear_down() {
+    match tear_down("searches_but_wont_replace_text_in_dry_run_mode") {
         Ok(()) => println!("tear_down ok"),
         Err(error) => eprintln!("tear_down error: {}", error),
     }
@@ -172,40 +160,33 @@ fn it_searches_but_wont_replace_text_in_dry_run_mode() {
 
 #[test]
 fn it_wont_replace_text_when_search_text_is_not_found() {
-    match setup() {
-        Ok(tests_directory) => {
-            let test_run_directory: PathBuf =
-                [tests_directory.display().to_string().as_str(), "test_run"]
-                    .iter()
-                    .collect();
-            let file_extensions: Vec<String> = vec!["json".to_string(), "js".to_string()];
-            let ignored_dirs: Vec<String> = Vec::new();
-            let search: String = "google".to_string();
-            let replace: String = "".to_string();
-            let dry_run: bool = false;
-            process_directory(
-                test_run_directory.as_path(),
-                &file_extensions,
-                &ignored_dirs,
-                search.as_str(),
-                replace.as_str(),
-                &dry_run,
-            );
-            assert_eq!(TOTAL_FILES_TO_BE_EDITED.load(Ordering::Relaxed), 0);
-            assert_eq!(TOTAL_FILES_EDITED_OK.load(Ordering::Relaxed), 0);
+    match setup("wont_replace_text_when_search_text_is_not_found") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["json", "js"])
+                .search("google")
+                .replace("")
+                .dry_run(false)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_found(), 0);
+            assert_eq!(report.files_edited(), 0);
             assert_results(
                 "original-2.json",
                 "actual-2.json",
-                tests_directory.as_path(),
+                test_run_directory.as_path(),
+            );
+            assert_results("original-3.js", "actual-3.js", test_run_directory.as_path());
+            assert_results(
+                "original-1.txt",
+                "actual-1.txt",
+                test_run_directory.as_path(),
             );
-            assert_results("original-3.js", "actual-3.js", tests_directory.as_path());
-            assert_results("original-1.txt", "actual-1.txt", tests_directory.as_path());
         }
         Err(error) => eprintln!("{}", error),
     }
-    TOTAL_FILES_TO_BE_EDITED.store(0, Ordering::Relaxed);
-    TOTAL_FILES_EDITED_OK.store(0, Ordering::Relaxed);
-    match tear_down() {
+    match tear_down("wont_replace_text_when_search_text_is_not_found") {
         Ok(()) => println!("tear_down ok"),
         Err(error) => eprintln!("tear_down error: {}", error),
     }
@@ -213,73 +194,1264 @@ fn it_wont_replace_text_when_search_text_is_not_found() {
 
 #[test]
 fn it_searches_and_replaces_text_from_all_files_when_file_extensions_are_omitted() {
-    match setup() {
-        Ok(tests_directory) => {
-            let test_run_directory: PathBuf =
-                [tests_directory.display().to_string().as_str(), "test_run"]
-                    .iter()
-                    .collect();
-            let file_extensions: Vec<String> = Vec::new();
-            let ignored_dirs: Vec<String> = Vec::new();
-            let search: String = "positive".to_string();
-            let replace: String = "negative".to_string();
-            let dry_run: bool = false;
-            process_directory(
-                test_run_directory.as_path(),
-                &file_extensions,
-                &ignored_dirs,
-                search.as_str(),
-                replace.as_str(),
-                &dry_run,
-            );
-            assert_eq!(TOTAL_FILES_TO_BE_EDITED.load(Ordering::Relaxed), 9);
-            assert_eq!(TOTAL_FILES_EDITED_OK.load(Ordering::Relaxed), 9);
+    match setup("searches_and_replaces_text_from_all_files_when_file_extensions_are_omitted") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(Vec::<String>::new())
+                .search("positive")
+                .replace("negative")
+                .dry_run(false)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_found(), 9);
+            assert_eq!(report.files_edited(), 9);
             assert_results(
                 "expected-21.json",
                 "actual-2.json",
-                tests_directory.as_path(),
+                test_run_directory.as_path(),
+            );
+            assert_results(
+                "expected-31.js",
+                "actual-3.js",
+                test_run_directory.as_path(),
+            );
+            assert_results(
+                "expected-1.txt",
+                "actual-1.txt",
+                test_run_directory.as_path(),
             );
-            assert_results("expected-31.js", "actual-3.js", tests_directory.as_path());
-            assert_results("expected-1.txt", "actual-1.txt", tests_directory.as_path());
         }
         Err(error) => eprintln!("{}", error),
     }
-    TOTAL_FILES_TO_BE_EDITED.store(0, Ordering::Relaxed);
-    TOTAL_FILES_EDITED_OK.store(0, Ordering::Relaxed);
-    match tear_down() {
+    match tear_down("searches_and_replaces_text_from_all_files_when_file_extensions_are_omitted") {
         Ok(()) => println!("tear_down ok"),
         Err(error) => eprintln!("tear_down error: {}", error),
     }
 }
 #[test]
 fn it_searches_and_replaces_text_from_all_files_except_files_in_ignored_dir1_and_dir2() {
-    match setup() {
-        Ok(tests_directory) => {
-            let test_run_directory: PathBuf =
-                [tests_directory.display().to_string().as_str(), "test_run"]
-                    .iter()
-                    .collect();
-            let file_extensions: Vec<String> = Vec::new();
-            let ignored_dirs: Vec<String> = vec!["dir1".to_string(), "dir2".to_string()];
-            let search: String = "positive".to_string();
-            let replace: String = "negative".to_string();
-            let dry_run: bool = false;
-            process_directory(
+    match setup("searches_and_replaces_text_from_all_files_except_files_in_ignored_dir1_and_dir2") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(Vec::<String>::new())
+                .ignored_dirs(["dir1", "dir2"])
+                .search("positive")
+                .replace("negative")
+                .dry_run(false)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_found(), 3);
+            assert_eq!(report.files_edited(), 3);
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down(
+        "searches_and_replaces_text_from_all_files_except_files_in_ignored_dir1_and_dir2",
+    ) {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_reports_match_counts_per_file_for_concurrent_runs() {
+    match setup("reports_match_counts_per_file_for_concurrent_runs") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["json", "js"])
+                .search("positive")
+                .replace("negative")
+                .dry_run(true)
+                .build();
+            let handles: Vec<std::thread::JoinHandle<Report>> = (0..2)
+                .map(|_| {
+                    let options: ReplaceOptions = options.clone();
+                    std::thread::spawn(move || {
+                        process_directory(&options).expect("invalid search text")
+                    })
+                })
+                .collect();
+            for handle in handles {
+                let report: Report = handle.join().expect("run panicked");
+                assert_eq!(report.files_found(), 6);
+                assert_eq!(report.files_edited(), 0);
+                assert_eq!(report.total_matches(), 21);
+                for file in &report.files {
+                    let expected_matches: usize =
+                        if file.path.extension().unwrap_or_default() == "js" {
+                            4
+                        } else {
+                            3
+                        };
+                    assert_eq!(file.matches, expected_matches);
+                    assert!(matches!(file.outcome, FileOutcome::Found));
+                }
+            }
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("reports_match_counts_per_file_for_concurrent_runs") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_reports_files_that_are_not_utf8_encoded() {
+    match setup("reports_files_that_are_not_utf8_encoded") {
+        Ok(test_run_directory) => {
+            let binary_file: std::path::PathBuf = test_run_directory.join("binary.txt");
+            std::fs::write(&binary_file, b"positive\n\xff\xfe positive\n").unwrap();
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["txt"])
+                .search("positive")
+                .replace("negative")
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_found(), 3);
+            assert_eq!(report.files_edited(), 3);
+            assert_eq!(report.files_failed(), 1);
+            let failed = report
+                .files
+                .iter()
+                .find(|file| matches!(file.outcome, FileOutcome::Failed(_)))
+                .unwrap();
+            match &failed.outcome {
+                FileOutcome::Failed(SarError::Decode { path, line }) => {
+                    assert_eq!(path, &binary_file);
+                    assert_eq!(*line, 2);
+                }
+                outcome => panic!("unexpected outcome: {:?}", outcome),
+            }
+            assert_eq!(
+                std::fs::read(&binary_file).unwrap(),
+                b"positive\n\xff\xfe positive\n"
+            );
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("reports_files_that_are_not_utf8_encoded") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_backs_up_edited_files_into_a_mirror_directory() {
+    match setup("backs_up_edited_files_into_a_mirror_directory") {
+        Ok(test_run_directory) => {
+            let backup_directory: std::path::PathBuf = test_run_directory.join("backup");
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["json", "js"])
+                .search("positive")
+                .replace("negative")
+                .backup(Backup::Directory(backup_directory.clone()))
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_edited(), 6);
+            for file in &report.files {
+                let relative_path = file.path.strip_prefix(&test_run_directory).unwrap();
+                assert_eq!(file.backup, Some(backup_directory.join(relative_path)));
+            }
+            assert_results(
+                "expected-21.json",
+                "actual-2.json",
+                test_run_directory.as_path(),
+            );
+            assert_results(
+                "original-2.json",
+                "actual-2.json",
+                backup_directory.as_path(),
+            );
+            assert_results("original-3.js", "actual-3.js", backup_directory.as_path());
+            // backups are not searched by a second run
+            let report: Report = process_directory(
+                &ReplaceOptions::builder()
+                    .directory(test_run_directory.as_path())
+                    .search("positive")
+                    .dry_run(true)
+                    .backup(Backup::Directory(backup_directory.clone()))
+                    .build(),
+            )
+            .expect("invalid search text");
+            assert_eq!(report.files_found(), 3);
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("backs_up_edited_files_into_a_mirror_directory") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_undoes_a_run_except_files_changed_since_the_run() {
+    match setup("undoes_a_run_except_files_changed_since_the_run") {
+        Ok(test_run_directory) => {
+            let state_directory: std::path::PathBuf = test_run_directory.join("state");
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["json", "js"])
+                .search("positive")
+                .replace("negative")
+                .journal_dir(state_directory.as_path())
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_edited(), 6);
+            let run_id: String = report.run_id.expect("journal not written");
+            let drifted_file: std::path::PathBuf = test_run_directory.join("actual-3.js");
+            let edited: Vec<u8> = std::fs::read(&drifted_file).unwrap();
+            std::fs::write(&drifted_file, "changed").unwrap();
+            let undo_report: UndoReport =
+                undo(state_directory.as_path(), None).expect("undo failed");
+            assert_eq!(undo_report.run_id, run_id);
+            assert_eq!(undo_report.files.len(), 6);
+            for file in &undo_report.files {
+                if file.path.ends_with("actual-3.js")
+                    && file
+                        .path
+                        .parent()
+                        .unwrap()
+                        .ends_with("undoes_a_run_except_files_changed_since_the_run")
+                {
+                    assert!(matches!(file.outcome, UndoOutcome::Drifted));
+                } else {
+                    assert!(matches!(file.outcome, UndoOutcome::Restored));
+                }
+            }
+            assert_results(
+                "original-2.json",
+                "actual-2.json",
+                test_run_directory.as_path(),
+            );
+            assert_eq!(std::fs::read_to_string(&drifted_file).unwrap(), "changed");
+            // a partial undo can be run again once the file is back
+            let journals = history(state_directory.as_path()).expect("history failed");
+            assert_eq!(journals.len(), 1);
+            assert!(!journals[0].undone);
+            std::fs::write(&drifted_file, edited).unwrap();
+            let undo_report: UndoReport =
+                undo(state_directory.as_path(), None).expect("undo failed");
+            assert_eq!(undo_report.run_id, run_id);
+            assert!(undo_report
+                .files
+                .iter()
+                .all(|file| matches!(file.outcome, UndoOutcome::Restored)));
+            assert_results("original-3.js", "actual-3.js", test_run_directory.as_path());
+            let journals = history(state_directory.as_path()).expect("history failed");
+            assert!(journals[0].undone);
+            assert!(matches!(
+                undo(state_directory.as_path(), None),
+                Err(SarError::RunNotFound { run_id: None })
+            ));
+            // dry runs are not journaled
+            let report: Report = process_directory(
+                &ReplaceOptions::builder()
+                    .directory(test_run_directory.as_path())
+                    .search("positive")
+                    .dry_run(true)
+                    .journal_dir(state_directory.as_path())
+                    .build(),
+            )
+            .expect("invalid search text");
+            assert_eq!(report.run_id, None);
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("undoes_a_run_except_files_changed_since_the_run") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_wont_edit_any_file_in_atomic_mode_when_a_file_fails() {
+    match setup("wont_edit_any_file_in_atomic_mode_when_a_file_fails") {
+        Ok(test_run_directory) => {
+            std::fs::write(
+                test_run_directory.join("dir2").join("binary.json"),
+                b"positive\n\xff\xfe\n",
+            )
+            .unwrap();
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["json", "js"])
+                .search("positive")
+                .replace("negative")
+                .atomic(true)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert!(report.aborted);
+            assert_eq!(report.files_found(), 6);
+            assert_eq!(report.files_edited(), 0);
+            assert_eq!(report.files_failed(), 1);
+            assert_results(
+                "original-2.json",
+                "actual-2.json",
+                test_run_directory.as_path(),
+            );
+            assert_results("original-3.js", "actual-3.js", test_run_directory.as_path());
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("wont_edit_any_file_in_atomic_mode_when_a_file_fails") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_rolls_back_edited_files_in_atomic_mode_when_a_late_step_fails() {
+    match setup("rolls_back_edited_files_in_atomic_mode_when_a_late_step_fails") {
+        Ok(test_run_directory) => {
+            // backups of files in dir2 fail, a file is in the way of the backup directory
+            let backup_directory: std::path::PathBuf = test_run_directory.join("backup");
+            std::fs::create_dir(&backup_directory).unwrap();
+            std::fs::write(backup_directory.join("dir2"), "").unwrap();
+            let state_directory: std::path::PathBuf = test_run_directory.join("state");
+            let mtime: filetime::FileTime = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+            for entry in walkdir::WalkDir::new(&test_run_directory) {
+                let entry: walkdir::DirEntry = entry.unwrap();
+                if entry.file_type().is_file() {
+                    filetime::set_file_mtime(entry.path(), mtime).unwrap();
+                }
+            }
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["json", "js"])
+                .search("positive")
+                .replace("negative")
+                .backup(Backup::Directory(backup_directory))
+                .journal_dir(state_directory.as_path())
+                .atomic(true)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert!(report.aborted);
+            // restored files get back their original mtime
+            assert!(report
+                .files
+                .iter()
+                .any(|file| matches!(file.outcome, FileOutcome::RolledBack)));
+            for file in &report.files {
+                let metadata: std::fs::Metadata = std::fs::metadata(&file.path).unwrap();
+                assert_eq!(
+                    filetime::FileTime::from_last_modification_time(&metadata),
+                    mtime
+                );
+            }
+            assert_eq!(report.files_edited(), 0);
+            assert_eq!(report.files_failed(), 1);
+            // the file whose backup failed was found all the same
+            assert_eq!(report.files_found(), report.files.len());
+            assert_eq!(report.run_id, None);
+            for file in &report.files {
+                assert!(matches!(
+                    file.outcome,
+                    FileOutcome::Found
+                        | FileOutcome::RolledBack
+                        | FileOutcome::Failed(SarError::Backup { .. })
+                ));
+            }
+            assert_results(
+                "original-2.json",
+                "actual-2.json",
                 test_run_directory.as_path(),
-                &file_extensions,
-                &ignored_dirs,
-                search.as_str(),
-                replace.as_str(),
-                &dry_run,
             );
-            assert_eq!(TOTAL_FILES_TO_BE_EDITED.load(Ordering::Relaxed), 3);
-            assert_eq!(TOTAL_FILES_EDITED_OK.load(Ordering::Relaxed), 3);
+            assert_results("original-3.js", "actual-3.js", test_run_directory.as_path());
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("rolls_back_edited_files_in_atomic_mode_when_a_late_step_fails") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_reports_a_patch_of_a_dry_run_without_editing_files() {
+    match setup("reports_a_patch_of_a_dry_run_without_editing_files") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["txt"])
+                .search(r"(?P<a>[a-z]{3})\.(?P<b>[a-z]{3})\.(?P<c>\d{3})")
+                .replace(r"$a.$b.com")
+                .dry_run(true)
+                .diff(0)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_found(), 3);
+            assert_eq!(report.files_edited(), 0);
+            let patch: String = report.patch();
+            for path in [
+                "actual-2.txt",
+                "dir2/actual-2.txt",
+                "dir1/dir11/actual-2.txt",
+            ] {
+                assert!(patch.contains(&format!("--- a/{}\n+++ b/{}\n", path, path)));
+            }
+            assert!(patch.contains(
+                "@@ -2,2 +2,2 @@\n-www.def.123\n-www.pqr.456\n+www.def.com\n+www.pqr.com\n"
+            ));
+            assert_results(
+                "original-2.txt",
+                "actual-2.txt",
+                test_run_directory.as_path(),
+            );
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("reports_a_patch_of_a_dry_run_without_editing_files") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_reports_matching_lines_with_spans_for_a_preview() {
+    match setup("reports_matching_lines_with_spans_for_a_preview") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.join("dir2"))
+                .file_extensions(["txt"])
+                .search(r"(?P<a>[a-z]{3})\.(?P<b>[a-z]{3})\.(?P<c>\d{3})")
+                .replace(r"$a.$b.com")
+                .dry_run(true)
+                .preview(true)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            let lines: Vec<(usize, &str, usize, usize, &str)> = report.files[0]
+                .lines
+                .iter()
+                .flat_map(|line| {
+                    line.spans.iter().map(move |span| {
+                        (
+                            line.line_number,
+                            line.line.as_str(),
+                            span.start,
+                            span.end,
+                            span.replacement.as_str(),
+                        )
+                    })
+                })
+                .collect();
+            assert_eq!(
+                lines,
+                vec![
+                    (2, "www.def.123\n", 0, 11, "www.def.com"),
+                    (3, "www.pqr.456\n", 0, 11, "www.pqr.com"),
+                ]
+            );
+            assert_results(
+                "original-2.txt",
+                "actual-2.txt",
+                test_run_directory.as_path(),
+            );
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("reports_matching_lines_with_spans_for_a_preview") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_replaces_only_confirmed_matches_in_interactive_mode() {
+    match setup("replaces_only_confirmed_matches_in_interactive_mode") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["txt"])
+                .search(r"(?P<a>[a-z]{3})\.(?P<b>[a-z]{3})\.(?P<c>\d{3})")
+                .replace(r"$a.$b.com")
+                .dry_run(false)
+                .build();
+            let mut asked: usize = 0;
+            let report: Report = process_directory_interactive(&options, |candidate| {
+                asked += 1;
+                if candidate.line_number == 2 {
+                    Decision::Yes
+                } else {
+                    Decision::No
+                }
+            })
+            .expect("invalid search text");
+            assert_eq!(asked, 6);
+            assert_eq!(report.files_edited(), 3);
+            assert_eq!(report.total_matches(), 3);
+            let content: String =
+                std::fs::read_to_string(test_run_directory.join("dir2").join("actual-2.txt"))
+                    .unwrap();
+            assert!(content.starts_with("www.abc.com\nwww.def.com\nwww.pqr.456\n"));
+            let report: Report = process_directory_interactive(&options, |_| Decision::Quit)
+                .expect("invalid search text");
+            assert!(report.files.is_empty());
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("replaces_only_confirmed_matches_in_interactive_mode") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_applies_only_selected_hunks_of_a_review() {
+    match setup("applies_only_selected_hunks_of_a_review") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["txt"])
+                .search(r"(?P<a>[a-z]{3})\.(?P<b>[a-z]{3})\.(?P<c>\d{3})")
+                .replace(r"$a.$b.com")
+                .dry_run(false)
+                .build();
+            let mut review: Review = review(&options).expect("invalid search text");
+            assert_eq!(review.files.len(), 3);
+            assert_eq!(review.selected(), 6);
+            for file in 0..review.files.len() {
+                if review.files[file].path.ends_with("dir2/actual-2.txt") {
+                    review.select_file(file, false);
+                } else {
+                    review.toggle(file, 1);
+                }
+            }
+            let report: Report = apply(&options, &review).expect("invalid search text");
+            assert_eq!(report.files_edited(), 2);
+            assert_eq!(report.total_matches(), 2);
+            let content: String =
+                std::fs::read_to_string(test_run_directory.join("dir2").join("actual-2.txt"))
+                    .unwrap();
+            assert!(content.starts_with("www.abc.com\nwww.def.123\nwww.pqr.456\n"));
+            let content: String =
+                std::fs::read_to_string(test_run_directory.join("actual-2.txt")).unwrap();
+            assert!(content.starts_with("www.abc.com\nwww.def.com\nwww.pqr.456\n"));
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("applies_only_selected_hunks_of_a_review") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_sorts_the_hunks_of_every_rule_by_line() {
+    match setup("sorts_the_hunks_of_every_rule_by_line") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.join("actual-2.txt"))
+                .search(r"\.com")
+                .replace(".org")
+                .rule(Rule::new("www", "web"))
+                .build();
+            let review: Review = review(&options).expect("invalid search text");
+            let lines: Vec<(usize, usize)> = review.files[0]
+                .hunks
+                .iter()
+                .map(|hunk| (hunk.line_number, hunk.spans[0].rule))
+                .collect();
+            assert_eq!(
+                lines,
+                vec![
+                    (1, 0),
+                    (1, 1),
+                    (2, 1),
+                    (3, 1),
+                    (4, 0),
+                    (4, 1),
+                    (5, 0),
+                    (5, 1)
+                ]
+            );
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("sorts_the_hunks_of_every_rule_by_line") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_searches_fixed_strings_and_replaces_literally() {
+    match setup("searches_fixed_strings_and_replaces_literally") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["js"])
+                .search("(num >= 0)")
+                .replace("($num)")
+                .fixed_strings(true)
+                .literal_replace(true)
+                .dry_run(false)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_edited(), 3);
+            assert_eq!(report.total_matches(), 3);
+            let content: String =
+                std::fs::read_to_string(test_run_directory.join("actual-3.js")).unwrap();
+            assert!(content.contains("\nif ($num) {\n"));
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("searches_fixed_strings_and_replaces_literally") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_matches_whole_words_ignoring_case() {
+    match setup("matches_whole_words_ignoring_case") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["js"])
+                .search("NÚMERO")
+                .replace("നമ്പർ")
+                .fixed_strings(true)
+                .ignore_case(true)
+                .word_regexp(true)
+                .dry_run(false)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_edited(), 3);
+            assert_eq!(report.total_matches(), 15);
+            assert_results(
+                "expected-32.js",
+                "actual-3.js",
+                test_run_directory.as_path(),
+            );
+            for (search, files_found) in [("sample", 3), ("Sample", 3), ("SAMPLE", 0)] {
+                let options: ReplaceOptions = ReplaceOptions::builder()
+                    .directory(test_run_directory.as_path())
+                    .file_extensions(["json"])
+                    .search(search)
+                    .smart_case(true)
+                    .dry_run(true)
+                    .build();
+                let report: Report = process_directory(&options).expect("invalid search text");
+                assert_eq!(report.files_found(), files_found);
+            }
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("matches_whole_words_ignoring_case") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_replaces_matches_spanning_several_lines_in_multiline_mode() {
+    match setup("replaces_matches_spanning_several_lines_in_multiline_mode") {
+        Ok(test_run_directory) => {
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["js"])
+                .search(r"(?s)\n+else \{\n.*?\n\}")
+                .replace("")
+                .multiline(true)
+                .preview(true)
+                .dry_run(false)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_edited(), 3);
+            assert_eq!(report.total_matches(), 3);
+            for file in &report.files {
+                assert_eq!(file.lines.len(), 1);
+                assert_eq!(file.lines[0].line_number, 22);
+                assert_eq!(file.lines[0].end_line_number, 25);
+            }
+            let content: String =
+                std::fs::read_to_string(test_run_directory.join("actual-3.js")).unwrap();
+            assert!(
+                content.ends_with("console.log(`The factorial of ${num} is ${result}`);\n}\n\n")
+            );
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("replaces_matches_spanning_several_lines_in_multiline_mode") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_applies_several_rules_in_a_single_walk() {
+    match setup("applies_several_rules_in_a_single_walk") {
+        Ok(test_run_directory) => {
+            let builder: ReplaceOptionsBuilder = ReplaceOptions::builder()
+                .directory(test_run_directory.as_path())
+                .file_extensions(["txt"])
+                .rule(Rule::new("Lorem", "Ipsum"))
+                .rule(Rule::new("Ipsum", "Dolor"));
+            // every rule sees the original text, "Lorem Ipsum" becomes "Ipsum Dolor"
+            let options: ReplaceOptions = builder
+                .clone()
+                .rule_order(RuleOrder::Simultaneous)
+                .dry_run(true)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_found(), 3);
+            assert_eq!(report.rule_matches(), vec![12, 12]);
+            // a rule sees the replacements of the rules before it
+            let options: ReplaceOptions = builder.dry_run(false).preview(true).build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_edited(), 3);
+            assert_eq!(report.rule_matches(), vec![12, 24]);
+            for file in &report.files {
+                assert_eq!(file.rule_matches, vec![4, 8]);
+                assert!(file.lines[0].spans.iter().all(|span| span.rule == 0));
+                assert!(file.lines[4].spans.iter().all(|span| span.rule == 1));
+            }
+            let content: String =
+                std::fs::read_to_string(test_run_directory.join("actual-1.txt")).unwrap();
+            assert!(!content.contains("Lorem") && !content.contains("Ipsum"));
+            assert_eq!(content.matches("Dolor Dolor").count(), 4);
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("applies_several_rules_in_a_single_walk") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_applies_a_rules_file() {
+    match setup("applies_a_rules_file") {
+        Ok(test_run_directory) => {
+            let rules_path: PathBuf = test_run_directory.join("rules.toml");
+            let rules: String = format!(
+                r#"
+                version = 1
+
+                [defaults]
+                directory = '{}'
+                extensions = ["txt"]
+                flags = ["word-regexp"]
+
+                [[rules]]
+                name = "lorem"
+                search = "lorem"
+                replace = "Dolor"
+                flags = ["ignore-case"]
+                include = ["dir2/**"]
+
+                [[rules]]
+                search = "positive"
+                replace = "negative"
+                mode = "literal"
+                ignored_dirs = ["dir11"]
+                "#,
+                test_run_directory.display()
+            );
+            std::fs::write(&rules_path, rules).unwrap();
+            let rules_file: RulesFile = load_rules(&rules_path).expect("invalid rules file");
+            assert_eq!(rules_file.names, vec![Some(String::from("lorem")), None]);
+            let options: ReplaceOptions = rules_file.options.dry_run(true).build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            let dir2: &FileReport = report
+                .files
+                .iter()
+                .find(|file| file.path.ends_with("dir2/actual-1.txt"))
+                .unwrap();
+            assert_eq!(dir2.rule_matches, vec![4, 1]);
+            let dir11: bool = report
+                .files
+                .iter()
+                .any(|file| file.path.starts_with(test_run_directory.join("dir1/dir11")));
+            assert!(!dir11);
+            assert_eq!(report.rule_matches(), vec![4, 2]);
+            // a relative directory does not depend on where sar is run from
+            std::fs::write(
+                &rules_path,
+                "version = 1\n[defaults]\ndirectory = \"dir2\"\n[[rules]]\nsearch = \"a\"\n",
+            )
+            .unwrap();
+            let rules_file: RulesFile = load_rules(&rules_path).expect("invalid rules file");
+            assert_eq!(
+                rules_file.options.build().directory(),
+                test_run_directory.join("dir2")
+            );
+            std::fs::write(
+                &rules_path,
+                "version = 1\n[[rules]]\nsearch = \"a(b\"\n[[rules]]\nsearch = \"\"\n",
+            )
+            .unwrap();
+            match load_rules(&rules_path) {
+                Err(SarError::RulesFile { errors, .. }) => {
+                    assert_eq!(errors.len(), 2);
+                    assert!(errors[0].starts_with("rule 1: Invalid search pattern"));
+                    assert_eq!(errors[1], "rule 2: search cannot be blank");
+                }
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("applies_a_rules_file") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_runs_rule_examples() {
+    match setup("runs_rule_examples") {
+        Ok(test_run_directory) => {
+            let rules_path: PathBuf = test_run_directory.join("rules.yaml");
+            std::fs::write(
+                &rules_path,
+                r#"
+version: 1
+defaults:
+  flags: [word-regexp]
+rules:
+  - name: rename
+    search: OrderItem
+    replace: LineItem
+    examples:
+      - input: "let item: OrderItem = OrderItem::new();\n"
+        output: "let item: LineItem = LineItem::new();\n"
+      - input: "OrderItems"
+        output: "LineItems"
+  - search: '(\w+)\.unwrap\(\)'
+    replace: "$1?"
+    flags: []
+    examples:
+      - input: "let a = b.unwrap();"
+        output: "let a = b?;"
+"#,
+            )
+            .unwrap();
+            let rules_file: RulesFile = load_rules(&rules_path).expect("invalid rules file");
+            let results: Vec<ExampleResult> =
+                run_examples(&rules_file).expect("invalid search text");
+            assert_eq!(results.len(), 3);
+            assert!(results[0].passed());
+            assert!(!results[1].passed());
+            assert_eq!((results[1].rule, results[1].example), (0, 1));
+            assert_eq!(results[1].actual, "OrderItems");
+            assert!(results[2].passed());
+            // the file is left untouched
+            assert!(std::fs::read_to_string(&rules_path)
+                .unwrap()
+                .contains("OrderItem::new"));
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("runs_rule_examples") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_replaces_the_keys_of_a_mapping() {
+    match setup("replaces_the_keys_of_a_mapping") {
+        Ok(test_run_directory) => {
+            let directory: PathBuf = test_run_directory.join("mapping");
+            std::fs::create_dir(&directory).unwrap();
+            std::fs::write(
+                directory.join("api.txt"),
+                "GET /api/v1/users\nGET /api/v1/users/me\n",
+            )
+            .unwrap();
+            let mapping_path: PathBuf = test_run_directory.join("mapping.tsv");
+            std::fs::write(
+                &mapping_path,
+                "/api/v1/users\t/api/v2/accounts\n/api/v1/users/me\t/api/v2/me\n/api/v1/orders\t/api/v2/orders\n",
+            )
+            .unwrap();
+            let map: Vec<(String, String)> = load_mapping(&mapping_path).expect("invalid mapping");
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(&directory)
+                .rule(Rule {
+                    map,
+                    ..Rule::default()
+                })
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.total_matches(), 2);
+            assert_eq!(report.key_matches(), vec![vec![1, 1, 0]]);
+            assert_eq!(
+                report.unmatched_keys,
+                vec![(0, String::from("/api/v1/orders"))]
+            );
+            assert_eq!(
+                std::fs::read_to_string(directory.join("api.txt")).unwrap(),
+                "GET /api/v2/accounts\nGET /api/v2/me\n"
+            );
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("replaces_the_keys_of_a_mapping") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_preserves_the_case_of_every_match() {
+    match setup("preserves_the_case_of_every_match") {
+        Ok(test_run_directory) => {
+            let directory: PathBuf = test_run_directory.join("case");
+            std::fs::create_dir(&directory).unwrap();
+            std::fs::write(
+                directory.join("widget.txt"),
+                "widget Widget WIDGET WidgetBox\n",
+            )
+            .unwrap();
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(&directory)
+                .search("widget")
+                .replace("gadget")
+                .preserve_case(true)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.total_matches(), 4);
+            assert_eq!(
+                std::fs::read_to_string(directory.join("widget.txt")).unwrap(),
+                "gadget Gadget GADGET GadgetBox\n"
+            );
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("preserves_the_case_of_every_match") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_renames_an_identifier_in_every_case_style() {
+    match setup("renames_an_identifier_in_every_case_style") {
+        Ok(test_run_directory) => {
+            let directory: PathBuf = test_run_directory.join("identifier");
+            std::fs::create_dir(&directory).unwrap();
+            std::fs::write(
+                directory.join("order.ts"),
+                "const orderItem: OrderItem = get_order_item('order-item', ORDER_ITEM);\nreorder_item(orderItems);\n",
+            )
+            .unwrap();
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(&directory)
+                .rename_identifier("order item", "line item")
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.total_matches(), 5);
+            assert_eq!(
+                std::fs::read_to_string(directory.join("order.ts")).unwrap(),
+                "const lineItem: LineItem = get_line_item('line-item', LINE_ITEM);\nreorder_item(orderItems);\n"
+            );
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(&directory)
+                .rename_identifier("line.item", "order item")
+                .build();
+            assert!(matches!(
+                process_directory(&options),
+                Err(SarError::InvalidIdentifier { .. })
+            ));
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("renames_an_identifier_in_every_case_style") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_applies_replace_text_filters() {
+    match setup("applies_replace_text_filters") {
+        Ok(test_run_directory) => {
+            let directory: PathBuf = test_run_directory.join("filters");
+            std::fs::create_dir(&directory).unwrap();
+            std::fs::write(
+                directory.join("links.txt"),
+                "link(7, \" release notes \")\n",
+            )
+            .unwrap();
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(&directory)
+                .search(r#"link\((?P<n>\d+), "(?P<title>[^"]*)"\)"#)
+                .replace(r"\U${title|trim}\E #${n|pad:3:0} /${title|trim|urlencode}")
+                .case_spans(true)
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.total_matches(), 1);
+            assert_eq!(
+                std::fs::read_to_string(directory.join("links.txt")).unwrap(),
+                "RELEASE NOTES #007 /release%20notes\n"
+            );
+            // without case spans backslashes are inserted as is
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(&directory)
+                .search("RELEASE NOTES")
+                .replace(r"C:\Users\Elena\new")
+                .build();
+            process_directory(&options).expect("invalid search text");
+            assert_eq!(
+                std::fs::read_to_string(directory.join("links.txt")).unwrap(),
+                "C:\\Users\\Elena\\new #007 /release%20notes\n"
+            );
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(&directory)
+                .search("notes")
+                .replace("${0|upper|shout}")
+                .build();
+            match process_directory(&options) {
+                Err(SarError::InvalidTemplate { message, .. }) => {
+                    assert!(message.starts_with("unknown filter `shout`"), "{}", message)
+                }
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("applies_replace_text_filters") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_applies_number_and_semver_filters() {
+    match setup("applies_number_and_semver_filters") {
+        Ok(test_run_directory) => {
+            let directory: PathBuf = test_run_directory.join("numbers");
+            std::fs::create_dir(&directory).unwrap();
+            std::fs::write(directory.join("release.txt"), "release v1.4.2 build 41\n").unwrap();
+            std::fs::write(
+                directory.join("next.txt"),
+                "notes\nrelease v1.4.2 build 7\nrelease vnext build 8\n",
+            )
+            .unwrap();
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(&directory)
+                .search(r"v(?P<ver>\S+) build (?P<n>\d+)")
+                .replace("v${ver|semver_bump:minor} build ${n|add:1|format:%05d}")
+                .build();
+            let report: Report = process_directory(&options).expect("invalid search text");
+            assert_eq!(report.files_edited(), 1);
+            assert_eq!(
+                std::fs::read_to_string(directory.join("release.txt")).unwrap(),
+                "release v1.5.0 build 00042\n"
+            );
+            // a match which cannot be replaced fails its file, which is left as is
+            let failed: &FileReport = report
+                .files
+                .iter()
+                .find(|file| file.path.ends_with("next.txt"))
+                .unwrap();
+            match &failed.outcome {
+                FileOutcome::Failed(SarError::Replace { line, message, .. }) => {
+                    assert_eq!(*line, 3);
+                    assert_eq!(
+                        message,
+                        "filter `semver_bump:minor` expects a semantic version like 1.2.3, found \"next\""
+                    );
+                }
+                outcome => panic!("unexpected outcome: {:?}", outcome),
+            }
+            assert_eq!(
+                std::fs::read_to_string(directory.join("next.txt")).unwrap(),
+                "notes\nrelease v1.4.2 build 7\nrelease vnext build 8\n"
+            );
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("applies_number_and_semver_filters") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_numbers_matches_with_counters() {
+    match setup("numbers_matches_with_counters") {
+        Ok(test_run_directory) => {
+            let directory: PathBuf = test_run_directory.join("counters");
+            std::fs::create_dir_all(directory.join("b")).unwrap();
+            std::fs::write(directory.join("c.txt"), "TODO\n").unwrap();
+            std::fs::write(directory.join("b").join("a.txt"), "TODO TODO\n").unwrap();
+            std::fs::write(directory.join("a.txt"), "TODO\n").unwrap();
+            let run = |counter_scope: CounterScope| {
+                ReplaceOptions::builder()
+                    .directory(&directory)
+                    .search("TODO")
+                    .replace("T${counter:start=100,step=10}-F${file_counter}")
+                    .counter_scope(counter_scope)
+                    .dry_run(true)
+                    .preview(true)
+                    .build()
+            };
+            let replacements = |report: &Report| -> Vec<String> {
+                report
+                    .files
+                    .iter()
+                    .flat_map(|file| &file.lines)
+                    .flat_map(|line| &line.spans)
+                    .map(|span| span.replacement.clone())
+                    .collect()
+            };
+            // files are walked sorted by name, a.txt before b/a.txt before c.txt
+            let report: Report = process_directory(&run(CounterScope::Global)).unwrap();
+            assert_eq!(
+                replacements(&report),
+                vec!["T100-F1", "T110-F2", "T120-F2", "T130-F3"]
+            );
+            let report: Report = process_directory(&run(CounterScope::File)).unwrap();
+            assert_eq!(
+                replacements(&report),
+                vec!["T100-F1", "T100-F2", "T110-F2", "T100-F3"]
+            );
+            // a declined match does not take a number
+            let mut asked: usize = 0;
+            let report: Report = process_directory_interactive(&run(CounterScope::Global), |_| {
+                asked += 1;
+                if asked == 2 {
+                    Decision::No
+                } else {
+                    Decision::Yes
+                }
+            })
+            .unwrap();
+            assert_eq!(replacements(&report), vec!["T100-F1", "T110-F2", "T120-F3"]);
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("numbers_matches_with_counters") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_replaces_with_a_script() {
+    match setup("replaces_with_a_script") {
+        Ok(test_run_directory) => {
+            let directory: PathBuf = test_run_directory.join("script");
+            std::fs::create_dir(&directory).unwrap();
+            let script: PathBuf = test_run_directory.join("transform.rhai");
+            std::fs::write(
+                &script,
+                r##"
+                    let codes = #{ red: "#f00", green: "#0f0" };
+                    let name = groups.name;
+                    if name == "purple" { throw "no code for " + name; }
+                    if name in codes { `${codes[name]} (${path.ends_with("colors.txt")}:${line})` }
+                "##,
+            )
+            .unwrap();
+            std::fs::write(
+                directory.join("colors.txt"),
+                "color red\ncolor blue\ncolor green\n",
+            )
+            .unwrap();
+            std::fs::write(directory.join("purple.txt"), "color red\ncolor purple\n").unwrap();
+            let options: ReplaceOptions = ReplaceOptions::builder()
+                .directory(&directory)
+                .search(r"color (?P<name>\w+)")
+                .replace_script(&script)
+                .build();
+            // compiled scripts can be moved to another thread
+            let matchers: Vec<Matcher> = build_matchers(&options).expect("invalid replace script");
+            assert_eq!(
+                std::thread::spawn(move || matchers.len()).join().unwrap(),
+                1
+            );
+            let report: Report = process_directory(&options).expect("invalid replace script");
+            assert_eq!(report.files_edited(), 1);
+            // () leaves blue alone
+            assert_eq!(
+                std::fs::read_to_string(directory.join("colors.txt")).unwrap(),
+                "#f00 (true:1)\ncolor blue\n#0f0 (true:3)\n"
+            );
+            // a match the script fails on fails its file, which is left as is
+            let failed: &FileReport = report
+                .files
+                .iter()
+                .find(|file| file.path.ends_with("purple.txt"))
+                .unwrap();
+            match &failed.outcome {
+                FileOutcome::Failed(SarError::Replace { line, message, .. }) => {
+                    assert_eq!(*line, 2);
+                    assert!(message.contains("no code for purple"));
+                }
+                outcome => panic!("unexpected outcome: {:?}", outcome),
+            }
+            assert_eq!(
+                std::fs::read_to_string(directory.join("purple.txt")).unwrap(),
+                "color red\ncolor purple\n"
+            );
+            let missing: ReplaceOptions = ReplaceOptions::builder()
+                .directory(&directory)
+                .search("color")
+                .replace_script(test_run_directory.join("missing.rhai"))
+                .build();
+            match process_directory(&missing) {
+                Err(SarError::Script { .. }) => (),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+    match tear_down("replaces_with_a_script") {
+        Ok(()) => println!("tear_down ok"),
+        Err(error) => eprintln!("tear_down error: {}", error),
+    }
+}
+
+#[test]
+fn it_asks_for_confirmation_only_on_a_terminal() {
+    match setup("asks_for_confirmation_only_on_a_terminal") {
+        Ok(test_run_directory) => {
+            let directory: PathBuf = test_run_directory.join("confirm");
+            std::fs::create_dir(&directory).unwrap();
+            let file: PathBuf = directory.join("a.txt");
+            std::fs::write(&file, "positive\n").unwrap();
+            // stdin of the tests is not a terminal
+            let sar = |arguments: &[&str], sar_yes: Option<&str>| {
+                let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_sar"));
+                command
+                    .arg("-d")
+                    .arg(&directory)
+                    .args(["-s", "positive", "-r", "negative"])
+                    .args(arguments)
+                    .env("SAR_STATE_DIR", test_run_directory.join("state"))
+                    .env_remove("SAR_YES")
+                    .stdin(std::process::Stdio::null());
+                if let Some(sar_yes) = sar_yes {
+                    command.env("SAR_YES", sar_yes);
+                }
+                command.output().unwrap()
+            };
+            let output: std::process::Output = sar(&[], None);
+            assert_eq!(output.status.code(), Some(1));
+            assert!(String::from_utf8_lossy(&output.stderr)
+                .contains("Standard input is not a terminal, cannot ask for confirmation"));
+            assert_eq!(std::fs::read_to_string(&file).unwrap(), "positive\n");
+            let output: std::process::Output = sar(&["--yes"], None);
+            assert!(output.status.success());
+            assert_eq!(std::fs::read_to_string(&file).unwrap(), "negative\n");
+            std::fs::write(&file, "positive\n").unwrap();
+            let output: std::process::Output = sar(&[], Some("true"));
+            assert!(output.status.success());
+            assert_eq!(std::fs::read_to_string(&file).unwrap(), "negative\n");
         }
         Err(error) => eprintln!("{}", error),
     }
-    TOTAL_FILES_TO_BE_EDITED.store(0, Ordering::Relaxed);
-    TOTAL_FILES_EDITED_OK.store(0, Ordering::Relaxed);
-    match tear_down() {
+    match tear_down("asks_for_confirmation_only_on_a_terminal") {
         Ok(()) => println!("tear_down ok"),
         Err(error) => eprintln!("tear_down error: {}", error),
     }